@@ -0,0 +1,2 @@
+-- OAuth2 scopes the user granted during their most recent login
+ALTER TABLE users ADD COLUMN IF NOT EXISTS granted_scopes TEXT[] NOT NULL DEFAULT '{}';
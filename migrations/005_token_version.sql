@@ -0,0 +1,2 @@
+-- Bumped to invalidate every JWT issued to a user before the bump
+ALTER TABLE users ADD COLUMN IF NOT EXISTS token_version INTEGER NOT NULL DEFAULT 0;
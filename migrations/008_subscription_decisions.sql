@@ -0,0 +1,15 @@
+-- Audit log of entitlement reconciliation decisions
+CREATE TABLE IF NOT EXISTS subscription_decisions (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    action VARCHAR(20) NOT NULL,
+    previous_tier VARCHAR(20) NOT NULL,
+    previous_source VARCHAR(20),
+    tier VARCHAR(20) NOT NULL,
+    source VARCHAR(20),
+    expires_at TIMESTAMP WITH TIME ZONE,
+    reason TEXT NOT NULL,
+    decided_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscription_decisions_user ON subscription_decisions(user_id, decided_at DESC);
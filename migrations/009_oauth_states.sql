@@ -0,0 +1,8 @@
+-- Nonces of OAuth state values that have been redeemed, so each state is single-use
+CREATE TABLE IF NOT EXISTS oauth_states (
+    nonce TEXT PRIMARY KEY,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_oauth_states_expires ON oauth_states(expires_at);
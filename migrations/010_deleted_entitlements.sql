@@ -0,0 +1,5 @@
+-- Entitlements that have been deleted, so late webhook events cannot bring them back
+CREATE TABLE IF NOT EXISTS deleted_entitlements (
+    entitlement_id BIGINT PRIMARY KEY,
+    deleted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
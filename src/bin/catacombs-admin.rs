@@ -0,0 +1,354 @@
+//! `catacombs-admin`: operate a catacombs database from the command line.
+//!
+//! Reads the same environment as the library (`Config::from_env`) plus
+//! `DATABASE_URL`. Build with `--features cli`.
+
+use anyhow::{bail, Context};
+use catacombs::{
+    entitlements,
+    models::{SubscriptionSource, SubscriptionTier, User, UserListParams},
+    reconciliation,
+    routes::admin::{AdminUserDetailResponse, AdminUserResponse, RECENT_DECISIONS_LIMIT},
+    AppState, Config, SqlxStorage,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Parser, Subcommand};
+use sqlx::postgres::PgPoolOptions;
+use tracing_subscriber::EnvFilter;
+
+/// Users fetched per page when walking every user.
+const PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "catacombs-admin",
+    version,
+    about = "Manage a catacombs database"
+)]
+struct Cli {
+    /// `PostgreSQL` connection string.
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    database_url: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run pending database migrations.
+    Migrate,
+    /// Inspect users.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Grant a user premium as a manual subscription.
+    GrantPremium {
+        user_id: i64,
+        /// When the grant ends, as RFC 3339 or YYYY-MM-DD (midnight UTC); omit for a lifetime grant.
+        #[arg(long, value_parser = parse_until)]
+        until: Option<DateTime<Utc>>,
+    },
+    /// Revoke a user's premium, recording the change as a manual subscription.
+    RevokePremium { user_id: i64 },
+    /// Clear a user's stored Discord tokens.
+    ClearTokens { user_id: i64 },
+    /// Re-encrypt every stored refresh token from ENCRYPTION_KEY to a new key.
+    RotateEncryptionKey {
+        /// The key to encrypt with from now on.
+        #[arg(long, env = "NEW_ENCRYPTION_KEY", hide_env_values = true)]
+        new_key: String,
+    },
+    /// Fetch entitlements from Discord and update subscription tiers.
+    SyncEntitlements {
+        /// Users to sync; syncs every user if none are given.
+        user_ids: Vec<i64>,
+    },
+    /// Page through every entitlement of the application and fix subscription tiers.
+    ReconcileEntitlements,
+}
+
+#[derive(Debug, Subcommand)]
+enum UserCommand {
+    /// Print a user with their roles and entitlements as JSON.
+    Show { user_id: i64 },
+    /// List users, newest first.
+    List {
+        /// Substring of the username or display name, or an exact user ID.
+        #[arg(long)]
+        search: Option<String>,
+        /// Only list users on this tier.
+        #[arg(long, value_parser = parse_tier)]
+        tier: Option<SubscriptionTier>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        #[arg(long, default_value_t = 0)]
+        offset: i64,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "warn".into()))
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&cli.database_url)
+        .await
+        .context("failed to connect to the database")?;
+    let storage = SqlxStorage::new(pool);
+
+    if let Command::Migrate = cli.command {
+        storage.migrate().await?;
+        println!("Migrations applied");
+        return Ok(());
+    }
+
+    let config = Config::from_env()?;
+    if let Command::RotateEncryptionKey { new_key } = &cli.command {
+        let count = storage
+            .rotate_encryption_key(&config.security.encryption_key, new_key)
+            .await?;
+        println!("Re-encrypted {count} refresh tokens; set ENCRYPTION_KEY to the new key");
+        return Ok(());
+    }
+
+    let state = AppState::new(config, storage);
+    match cli.command {
+        Command::Migrate | Command::RotateEncryptionKey { .. } => unreachable!(),
+        Command::User(UserCommand::Show { user_id }) => {
+            let user = load_user(&state, user_id).await?;
+            let detail = AdminUserDetailResponse {
+                user: user.into(),
+                roles: state.storage.get_user_roles(user_id).await?,
+                entitlements: state.storage.get_user_entitlements(user_id).await?,
+                subscription_decisions: state
+                    .storage
+                    .get_subscription_decisions(user_id, RECENT_DECISIONS_LIMIT)
+                    .await?,
+            };
+            println!("{}", serde_json::to_string_pretty(&detail)?);
+        }
+        Command::User(UserCommand::List {
+            search,
+            tier,
+            limit,
+            offset,
+        }) => {
+            let users = state
+                .storage
+                .list_users(UserListParams {
+                    search: search.as_deref(),
+                    tier,
+                    limit,
+                    offset,
+                })
+                .await?;
+            print_users(&users);
+        }
+        Command::GrantPremium { user_id, until } => {
+            if until.is_some_and(|until| until <= Utc::now()) {
+                bail!("--until must be in the future");
+            }
+            set_subscription(&state, user_id, SubscriptionTier::Premium, until).await?;
+            println!("Granted premium to user {user_id}");
+        }
+        Command::RevokePremium { user_id } => {
+            set_subscription(&state, user_id, SubscriptionTier::Free, None).await?;
+            println!("Revoked premium from user {user_id}");
+        }
+        Command::ClearTokens { user_id } => {
+            load_user(&state, user_id).await?;
+            state.storage.clear_user_tokens(user_id).await?;
+            println!("Cleared Discord tokens of user {user_id}");
+        }
+        Command::SyncEntitlements { user_ids } => {
+            let user_ids = if user_ids.is_empty() {
+                all_user_ids(&state).await?
+            } else {
+                user_ids
+            };
+            sync_entitlements(&state, &user_ids).await?;
+        }
+        Command::ReconcileEntitlements => {
+            let report = reconciliation::reconcile_all_entitlements(&state).await?;
+            for decision in &report.decisions {
+                println!(
+                    "{}\t{:?}\t{} -> {}\t{}",
+                    decision.user_id,
+                    decision.action,
+                    tier_name(decision.previous_tier),
+                    tier_name(decision.tier),
+                    decision.reason
+                );
+            }
+            println!("Reconciled {report}");
+            if report.errors > 0 {
+                bail!("{} errors during reconciliation", report.errors);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch a user, failing if they do not exist.
+async fn load_user(state: &AppState, user_id: i64) -> anyhow::Result<User> {
+    state
+        .storage
+        .get_user(user_id, &state.config.security.encryption_key)
+        .await?
+        .with_context(|| format!("user {user_id} not found"))
+}
+
+async fn set_subscription(
+    state: &AppState,
+    user_id: i64,
+    tier: SubscriptionTier,
+    expires_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    load_user(state, user_id).await?;
+    state
+        .storage
+        .update_subscription(user_id, tier, SubscriptionSource::Manual, expires_at)
+        .await?;
+    Ok(())
+}
+
+async fn all_user_ids(state: &AppState) -> anyhow::Result<Vec<i64>> {
+    let mut user_ids = Vec::new();
+    loop {
+        let page = state
+            .storage
+            .list_users(UserListParams {
+                limit: PAGE_SIZE,
+                offset: user_ids.len() as i64,
+                ..UserListParams::default()
+            })
+            .await?;
+        let done = (page.len() as i64) < PAGE_SIZE;
+        user_ids.extend(page.iter().map(|user| user.user_id));
+        if done {
+            return Ok(user_ids);
+        }
+    }
+}
+
+/// Sync each user, reporting failures without stopping.
+async fn sync_entitlements(state: &AppState, user_ids: &[i64]) -> anyhow::Result<()> {
+    let mut failed = 0;
+    for &user_id in user_ids {
+        match entitlements::sync_user_entitlements(state, user_id).await {
+            Ok(tier) => println!("{user_id}\t{}", tier_name(tier)),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{user_id}\tfailed: {e:#}");
+            }
+        }
+    }
+
+    println!(
+        "Synced {} of {} users",
+        user_ids.len() - failed,
+        user_ids.len()
+    );
+    if failed > 0 {
+        bail!("{failed} users failed to sync");
+    }
+    Ok(())
+}
+
+fn print_users(users: &[User]) {
+    println!(
+        "{:<20}  {:<32}  {:<8}  {:<8}  EXPIRES",
+        "USER ID", "USERNAME", "TIER", "SOURCE"
+    );
+    for user in users.iter().cloned().map(AdminUserResponse::from) {
+        println!(
+            "{:<20}  {:<32}  {:<8}  {:<8}  {}",
+            user.user_id,
+            user.username,
+            tier_name(user.subscription_tier),
+            user.subscription_source.map_or("-", source_name),
+            user.subscription_expires_at
+                .map_or_else(|| "-".to_string(), |expires| expires.to_rfc3339()),
+        );
+    }
+}
+
+fn tier_name(tier: SubscriptionTier) -> &'static str {
+    match tier {
+        SubscriptionTier::Free => "free",
+        SubscriptionTier::Premium => "premium",
+    }
+}
+
+fn source_name(source: SubscriptionSource) -> &'static str {
+    match source {
+        SubscriptionSource::Discord => "discord",
+        SubscriptionSource::Manual => "manual",
+        SubscriptionSource::External => "external",
+    }
+}
+
+fn parse_tier(value: &str) -> Result<SubscriptionTier, String> {
+    match value {
+        "free" => Ok(SubscriptionTier::Free),
+        "premium" => Ok(SubscriptionTier::Premium),
+        _ => Err("expected free or premium".to_string()),
+    }
+}
+
+/// Parse an RFC 3339 timestamp, or a date as midnight UTC.
+fn parse_until(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+        .map_err(|_| "expected an RFC 3339 timestamp or YYYY-MM-DD".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "catacombs-admin",
+            "--database-url",
+            "postgres://localhost/test",
+            "user",
+            "list",
+            "--tier",
+            "premium",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::User(UserCommand::List {
+                tier: Some(SubscriptionTier::Premium),
+                limit: 50,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_until() {
+        let date = parse_until("2030-01-02").unwrap();
+        assert_eq!(date.to_rfc3339(), "2030-01-02T00:00:00+00:00");
+
+        let timestamp = parse_until("2030-01-02T03:04:05+02:00").unwrap();
+        assert_eq!(timestamp.to_rfc3339(), "2030-01-02T01:04:05+00:00");
+
+        assert!(parse_until("next week").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! `catacombs-server`: run the auth routes as a standalone service.
+//!
+//! Reads `Config::from_env` and listens on `HOST`:`PORT`. With `sqlx-storage` it
+//! connects to `DATABASE_URL` and runs migrations on start; with only
+//! `memory-storage` it keeps everything in memory. Unless
+//! `ENTITLEMENT_RECONCILE_INTERVAL_SECONDS` is 0, it also runs the entitlement
+//! reconciliation job. Build with `--features server`.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use axum::{
+    http::{header, HeaderName, HeaderValue, Method},
+    Router,
+};
+use catacombs::{
+    cookies::CSRF_HEADER, reconciliation, routes, AppState, Config, ServerConfig, SharedState,
+};
+use tower_http::{
+    cors::{AllowOrigin, Any, CorsLayer},
+    trace::TraceLayer,
+};
+use tracing_subscriber::EnvFilter;
+
+#[cfg(not(any(feature = "sqlx-storage", feature = "memory-storage")))]
+compile_error!("catacombs-server needs the sqlx-storage or memory-storage feature");
+
+/// How long in-flight requests get to finish after a shutdown signal.
+///
+/// Server-Sent Event streams never finish on their own, so they are cut off
+/// when this runs out.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| "info,tower_http=debug".into()),
+        )
+        .init();
+
+    let config = Config::from_env()?;
+    let address = config.server.bind_address();
+    let state = Arc::new(AppState::new(config, connect_storage().await?));
+    let reconcile_interval = state.config.discord.entitlement_reconcile_interval_seconds;
+    let reconciler = (reconcile_interval > 0).then(|| {
+        reconciliation::spawn_reconciliation_job(
+            state.clone(),
+            Duration::from_secs(reconcile_interval),
+        )
+    });
+    let app = app(state)?;
+
+    let listener = tokio::net::TcpListener::bind(&address)
+        .await
+        .with_context(|| format!("failed to bind {address}"))?;
+    tracing::info!("Listening on {}", address);
+
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(());
+    let mut server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                shutdown_rx.changed().await.ok();
+            })
+            .await
+    });
+
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        () = shutdown_signal() => {}
+    }
+
+    tracing::info!("Shutting down");
+    shutdown_tx.send(()).ok();
+    if let Some(reconciler) = reconciler {
+        reconciler.abort();
+    }
+    match tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, server).await {
+        Ok(result) => result??,
+        Err(_) => tracing::warn!("Closing connections still open after the grace period"),
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlx-storage")]
+async fn connect_storage() -> anyhow::Result<catacombs::SqlxStorage> {
+    let url = std::env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let pool = sqlx::PgPool::connect(&url)
+        .await
+        .context("failed to connect to the database")?;
+
+    let storage = catacombs::SqlxStorage::new(pool);
+    storage.migrate().await?;
+    Ok(storage)
+}
+
+#[cfg(all(feature = "memory-storage", not(feature = "sqlx-storage")))]
+async fn connect_storage() -> anyhow::Result<catacombs::MemoryStorage> {
+    tracing::warn!("Using in-memory storage; all users and sessions are lost on shutdown");
+    Ok(catacombs::MemoryStorage::new())
+}
+
+/// Mount the auth and webhook event routes under the configured prefix and the
+/// JWKS at the root.
+fn app(state: SharedState) -> anyhow::Result<Router> {
+    let server = &state.config.server;
+    let cors = cors_layer(server)?;
+    let routes = routes::auth_router().merge(routes::events_router());
+    let router = match server.route_prefix.as_str() {
+        "" => Router::new().merge(routes),
+        prefix => Router::new().nest(prefix, routes),
+    };
+
+    Ok(router
+        .merge(routes::jwks_router())
+        .layer(cors)
+        .layer(TraceLayer::new_for_http())
+        .with_state(state))
+}
+
+/// Allow the configured origins, with credentials so cookie sessions work.
+fn cors_layer(server: &ServerConfig) -> anyhow::Result<CorsLayer> {
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(CSRF_HEADER),
+        ]);
+
+    if server
+        .cors_allowed_origins
+        .iter()
+        .any(|origin| origin == "*")
+    {
+        return Ok(cors.allow_origin(Any));
+    }
+
+    let origins = server
+        .cors_allowed_origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin)
+                .with_context(|| format!("invalid origin in CORS_ALLOWED_ORIGINS: {origin}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(cors
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true))
+}
+
+/// Resolve on Ctrl+C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::Service;
+
+    fn test_state(route_prefix: &str, cors_allowed_origins: &[&str]) -> SharedState {
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "discord": {"client_id": "1", "client_secret": "s", "redirect_uri": "r", "bot_token": "b"},
+            "security": {"jwt_secret": "secret", "encryption_key": "key"},
+        }))
+        .unwrap();
+        config.server.route_prefix = route_prefix.to_string();
+        config.server.cors_allowed_origins = cors_allowed_origins
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        #[cfg(feature = "sqlx-storage")]
+        let storage = catacombs::SqlxStorage::new(
+            sqlx::PgPool::connect_lazy("postgres://localhost/catacombs").unwrap(),
+        );
+        #[cfg(not(feature = "sqlx-storage"))]
+        let storage = catacombs::MemoryStorage::new();
+
+        Arc::new(AppState::new(config, storage))
+    }
+
+    async fn status(app: &mut Router, request: Request<Body>) -> u16 {
+        app.call(request).await.unwrap().status().as_u16()
+    }
+
+    #[tokio::test]
+    async fn test_app_routes_and_cors() {
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let mut nested = app(test_state("/auth", &["https://app.example"])).unwrap();
+        assert_eq!(status(&mut nested, get("/auth/me")).await, 401);
+        assert_eq!(status(&mut nested, get("/me")).await, 404);
+        let unsigned = Request::post("/auth/webhook-events")
+            .body(Body::from("{}"))
+            .unwrap();
+        assert_eq!(status(&mut nested, unsigned).await, 401);
+        assert_eq!(
+            status(&mut nested, get("/.well-known/jwks.json")).await,
+            200
+        );
+
+        let preflight = |origin: &str| {
+            Request::options("/auth/me")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = nested.call(preflight("https://app.example")).await.unwrap();
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example"
+        );
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+        let response = nested
+            .call(preflight("https://evil.example"))
+            .await
+            .unwrap();
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let mut root = app(test_state("", &["*"])).unwrap();
+        assert_eq!(status(&mut root, get("/me")).await, 401);
+    }
+}
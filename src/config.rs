@@ -28,15 +28,219 @@ pub struct DiscordConfig {
     /// Optional SKU ID for premium subscription entitlements.
     #[serde(default)]
     pub premium_sku_id: Option<i64>,
+    /// `OAuth2` scopes requested by the server-side login flow.
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+    /// Where `/callback` redirects the browser after a successful login.
+    #[serde(default = "default_post_login_redirect_uri")]
+    pub post_login_redirect_uri: String,
+    /// Base URL of Discord's REST API, overridable to point at a mock server.
+    #[serde(default = "default_api_base_url")]
+    pub api_base_url: String,
+    /// Times a rate-limited Discord request is retried before giving up.
+    #[serde(default = "default_rate_limit_max_retries")]
+    pub rate_limit_max_retries: u32,
+    /// Longest a Discord request waits out a rate limit, in seconds, before giving up.
+    #[serde(default = "default_rate_limit_max_wait_seconds")]
+    pub rate_limit_max_wait_seconds: u64,
+    /// Hex-encoded Ed25519 public key of the application, used to verify
+    /// webhook events and interactions Discord sends.
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Seconds between runs of the entitlement reconciliation job, 0 to disable it.
+    #[serde(default = "default_entitlement_reconcile_interval_seconds")]
+    pub entitlement_reconcile_interval_seconds: u64,
+}
+
+impl DiscordConfig {
+    /// The URL of a Discord API endpoint, e.g. `api_url("/users/@me")`.
+    #[must_use]
+    pub fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.api_base_url.trim_end_matches('/'), path)
+    }
 }
 
 /// Security configuration.
 #[derive(Debug, Clone, Deserialize)]
 pub struct SecurityConfig {
-    /// Secret key for JWT token signing.
+    /// Secret key for JWT token signing (HS256) and `OAuth2` `state` signing.
     pub jwt_secret: String,
+    /// Algorithm used to sign JWTs.
+    #[serde(default)]
+    pub jwt_algorithm: JwtAlgorithm,
+    /// PEM-encoded private key used to sign JWTs with an asymmetric algorithm.
+    #[serde(default)]
+    pub jwt_private_key: Option<String>,
+    /// PEM-encoded public key matching `jwt_private_key`, published via JWKS.
+    #[serde(default)]
+    pub jwt_public_key: Option<String>,
+    /// Key ID (`kid`) written into the header of tokens signed with `jwt_secret`.
+    #[serde(default)]
+    pub jwt_key_id: Option<String>,
+    /// Retired signing keys that are still accepted when verifying tokens.
+    #[serde(default)]
+    pub jwt_previous_keys: Vec<JwtVerificationKey>,
+    /// Lifetime of issued access tokens, in seconds.
+    #[serde(default = "default_jwt_ttl_seconds")]
+    pub jwt_ttl_seconds: i64,
+    /// Issuer (`iss`) written into every token and required when validating.
+    #[serde(default = "default_jwt_issuer")]
+    pub jwt_issuer: String,
+    /// Audience (`aud`) written into every token and required when validating.
+    #[serde(default = "default_jwt_audience")]
+    pub jwt_audience: String,
+    /// Clock skew tolerated when checking `exp` and `nbf`, in seconds.
+    #[serde(default = "default_jwt_leeway_seconds")]
+    pub jwt_leeway_seconds: u64,
+    /// Lifetime of first-party refresh tokens, in seconds.
+    #[serde(default = "default_refresh_token_ttl_seconds")]
+    pub refresh_token_ttl_seconds: i64,
     /// Base64-encoded 32-byte key for AES-256-GCM encryption of refresh tokens.
     pub encryption_key: String,
+    /// Reject code exchanges that do not carry a signed `state` and PKCE verifier.
+    #[serde(default)]
+    pub require_pkce: bool,
+    /// Deliver tokens in `HttpOnly` cookies instead of response bodies, and
+    /// require a CSRF token on unsafe requests authenticated by cookie.
+    #[serde(default)]
+    pub cookie_sessions: bool,
+    /// `SameSite` attribute of the session cookies.
+    #[serde(default)]
+    pub cookie_same_site: CookieSameSite,
+    /// Also accept JWTs in a `?token=` query parameter. Off by default, since
+    /// URLs end up in proxy and access logs; prefer WebSocket and SSE tickets.
+    #[serde(default)]
+    pub allow_query_token: bool,
+}
+
+/// `SameSite` policies for the session cookies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum CookieSameSite {
+    /// Only sent on same-site requests.
+    Strict,
+    /// Also sent on top-level cross-site navigations.
+    #[default]
+    Lax,
+    /// Sent on all requests; needed when the front-end is on another site.
+    None,
+}
+
+impl std::str::FromStr for CookieSameSite {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Strict" => Ok(Self::Strict),
+            "Lax" => Ok(Self::Lax),
+            "None" => Ok(Self::None),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Algorithms supported for signing JWTs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum JwtAlgorithm {
+    /// HMAC with SHA-256, using `SecurityConfig::jwt_secret`.
+    #[default]
+    HS256,
+    /// RSASSA-PKCS1-v1_5 with SHA-256.
+    RS256,
+    /// ECDSA with P-256 and SHA-256.
+    ES256,
+    /// Ed25519.
+    EdDSA,
+}
+
+impl JwtAlgorithm {
+    /// Returns true if this algorithm signs with a private key and verifies with a public key.
+    #[must_use]
+    pub fn is_asymmetric(&self) -> bool {
+        !matches!(self, Self::HS256)
+    }
+}
+
+impl std::str::FromStr for JwtAlgorithm {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(Self::HS256),
+            "RS256" => Ok(Self::RS256),
+            "ES256" => Ok(Self::ES256),
+            "EdDSA" => Ok(Self::EdDSA),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A retired JWT signing key that is still accepted for verification.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtVerificationKey {
+    /// Key ID (`kid`) the key signed tokens with.
+    pub kid: String,
+    /// Algorithm the key signed tokens with.
+    #[serde(default)]
+    pub algorithm: JwtAlgorithm,
+    /// HMAC secret for `HS256`, or the PEM-encoded public key otherwise.
+    pub key: String,
+}
+
+impl JwtVerificationKey {
+    /// Parse a comma-separated list of `kid:secret` HMAC key pairs.
+    ///
+    /// # Errors
+    ///    - Returns `ConfigError::InvalidEnv` if an entry has no `:` separator.
+    pub fn parse_list(value: &str, var: &'static str) -> Result<Vec<Self>, ConfigError> {
+        split_list(value)
+            .map(|entry| {
+                entry
+                    .split_once(':')
+                    .map(|(kid, secret)| Self {
+                        kid: kid.to_string(),
+                        algorithm: JwtAlgorithm::HS256,
+                        key: secret.to_string(),
+                    })
+                    .ok_or(ConfigError::InvalidEnv(var))
+            })
+            .collect()
+    }
+
+    /// Parse a comma-separated list of `kid:ALGORITHM:path` public key entries,
+    /// reading each PEM-encoded public key from `path`.
+    ///
+    /// # Errors
+    ///    - Returns `ConfigError::InvalidEnv` if an entry is malformed or names an
+    ///      unsupported algorithm.
+    ///    - Returns `ConfigError::ReadFile` if a key file cannot be read.
+    pub fn parse_public_key_list(value: &str, var: &'static str) -> Result<Vec<Self>, ConfigError> {
+        split_list(value)
+            .map(|entry| {
+                let mut parts = entry.splitn(3, ':');
+                let (Some(kid), Some(algorithm), Some(path)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(ConfigError::InvalidEnv(var));
+                };
+                let algorithm = algorithm
+                    .parse::<JwtAlgorithm>()
+                    .map_err(|()| ConfigError::InvalidEnv(var))?;
+                Ok(Self {
+                    kid: kid.to_string(),
+                    algorithm,
+                    key: std::fs::read_to_string(path)
+                        .map_err(|e| ConfigError::ReadFile(var, e))?,
+                })
+            })
+            .collect()
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
 }
 
 /// Server configuration.
@@ -48,6 +252,65 @@ pub struct ServerConfig {
     /// Port to listen on.
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Path `catacombs-server` mounts the auth routes under; empty mounts them at the root.
+    #[serde(default = "default_route_prefix")]
+    pub route_prefix: String,
+    /// Origins `catacombs-server` allows cross-origin requests from; `*` allows any
+    /// origin without credentials.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+}
+
+impl ServerConfig {
+    /// The `host:port` address to listen on.
+    #[must_use]
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+fn default_scopes() -> Vec<String> {
+    vec!["identify".to_string()]
+}
+
+fn default_post_login_redirect_uri() -> String {
+    "/".to_string()
+}
+
+fn default_api_base_url() -> String {
+    crate::oauth::DISCORD_API_BASE_URL.to_string()
+}
+
+fn default_rate_limit_max_retries() -> u32 {
+    3
+}
+
+fn default_rate_limit_max_wait_seconds() -> u64 {
+    10
+}
+
+fn default_entitlement_reconcile_interval_seconds() -> u64 {
+    3600
+}
+
+fn default_jwt_ttl_seconds() -> i64 {
+    15 * 60
+}
+
+fn default_jwt_issuer() -> String {
+    "catacombs".to_string()
+}
+
+fn default_jwt_audience() -> String {
+    "catacombs".to_string()
+}
+
+fn default_jwt_leeway_seconds() -> u64 {
+    60
+}
+
+fn default_refresh_token_ttl_seconds() -> i64 {
+    30 * 24 * 60 * 60
 }
 
 fn default_host() -> String {
@@ -58,11 +321,17 @@ fn default_port() -> u16 {
     3000
 }
 
+fn default_route_prefix() -> String {
+    "/auth".to_string()
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             host: default_host(),
             port: default_port(),
+            route_prefix: default_route_prefix(),
+            cors_allowed_origins: Vec::new(),
         }
     }
 }
@@ -76,10 +345,38 @@ impl Config {
     /// - `DISCORD_REDIRECT_URI`
     /// - `DISCORD_BOT_TOKEN`
     /// - `DISCORD_PREMIUM_SKU_ID` (optional)
+    /// - `DISCORD_SCOPES` (optional, space-separated, defaults to "identify")
+    /// - `POST_LOGIN_REDIRECT_URI` (optional, defaults to "/")
+    /// - `DISCORD_API_BASE_URL` (optional, defaults to Discord's v10 API)
+    /// - `DISCORD_RATE_LIMIT_MAX_RETRIES` (optional, defaults to 3)
+    /// - `DISCORD_RATE_LIMIT_MAX_WAIT_SECONDS` (optional, defaults to 10)
+    /// - `DISCORD_PUBLIC_KEY` (optional, hex; required to receive webhook events and interactions)
+    /// - `ENTITLEMENT_RECONCILE_INTERVAL_SECONDS` (optional, defaults to 3600, 0 disables)
     /// - `JWT_SECRET`
+    /// - `JWT_ALGORITHM` (optional, `HS256`, `RS256`, `ES256` or `EdDSA`, defaults to `HS256`)
+    /// - `JWT_PRIVATE_KEY_FILE` (required for asymmetric algorithms, PEM private key)
+    /// - `JWT_PUBLIC_KEY_FILE` (required for asymmetric algorithms, PEM public key)
+    /// - `JWT_KEY_ID` (optional, `kid` of the current signing key)
+    /// - `JWT_PREVIOUS_KEYS` (optional, comma-separated `kid:secret` pairs)
+    /// - `JWT_PREVIOUS_PUBLIC_KEYS` (optional, comma-separated `kid:ALGORITHM:path` entries)
+    /// - `JWT_TTL_SECONDS` (optional, defaults to 900)
+    /// - `JWT_ISSUER` (optional, defaults to "catacombs")
+    /// - `JWT_AUDIENCE` (optional, defaults to "catacombs")
+    /// - `JWT_LEEWAY_SECONDS` (optional, defaults to 60)
+    /// - `REFRESH_TOKEN_TTL_SECONDS` (optional, defaults to 2592000)
     /// - `ENCRYPTION_KEY`
+    /// - `REQUIRE_PKCE` (optional, defaults to false)
+    /// - `COOKIE_SESSIONS` (optional, defaults to false)
+    /// - `COOKIE_SAME_SITE` (optional, `Strict`, `Lax` or `None`, defaults to `Lax`)
+    /// - `ALLOW_QUERY_TOKEN` (optional, defaults to false)
     /// - `HOST` (optional, defaults to "0.0.0.0")
     /// - `PORT` (optional, defaults to 3000)
+    /// - `ROUTE_PREFIX` (optional, defaults to "/auth")
+    /// - `CORS_ALLOWED_ORIGINS` (optional, comma-separated)
+    ///
+    /// # Errors
+    ///    - Returns `ConfigError` if a required variable is missing, a value is
+    ///      invalid, a key file cannot be read or a JWT key cannot be parsed.
     pub fn from_env() -> Result<Self, ConfigError> {
         let discord = DiscordConfig {
             client_id: std::env::var("DISCORD_CLIENT_ID")
@@ -93,13 +390,69 @@ impl Config {
             premium_sku_id: std::env::var("DISCORD_PREMIUM_SKU_ID")
                 .ok()
                 .and_then(|s| s.parse().ok()),
+            scopes: std::env::var("DISCORD_SCOPES")
+                .map(|s| s.split_whitespace().map(String::from).collect())
+                .unwrap_or_else(|_| default_scopes()),
+            post_login_redirect_uri: std::env::var("POST_LOGIN_REDIRECT_URI")
+                .unwrap_or_else(|_| default_post_login_redirect_uri()),
+            api_base_url: std::env::var("DISCORD_API_BASE_URL")
+                .unwrap_or_else(|_| default_api_base_url()),
+            rate_limit_max_retries: parse_env("DISCORD_RATE_LIMIT_MAX_RETRIES")?
+                .unwrap_or_else(default_rate_limit_max_retries),
+            rate_limit_max_wait_seconds: parse_env("DISCORD_RATE_LIMIT_MAX_WAIT_SECONDS")?
+                .unwrap_or_else(default_rate_limit_max_wait_seconds),
+            public_key: match std::env::var("DISCORD_PUBLIC_KEY") {
+                Ok(value) => {
+                    crate::discord::parse_public_key(&value)
+                        .map_err(|_| ConfigError::InvalidEnv("DISCORD_PUBLIC_KEY"))?;
+                    Some(value)
+                }
+                Err(_) => None,
+            },
+            entitlement_reconcile_interval_seconds: parse_env(
+                "ENTITLEMENT_RECONCILE_INTERVAL_SECONDS",
+            )?
+            .unwrap_or_else(default_entitlement_reconcile_interval_seconds),
         };
 
         let security = SecurityConfig {
             jwt_secret: std::env::var("JWT_SECRET")
                 .map_err(|_| ConfigError::MissingEnv("JWT_SECRET"))?,
+            jwt_algorithm: match std::env::var("JWT_ALGORITHM") {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|()| ConfigError::InvalidEnv("JWT_ALGORITHM"))?,
+                Err(_) => JwtAlgorithm::default(),
+            },
+            jwt_private_key: read_env_file("JWT_PRIVATE_KEY_FILE")?,
+            jwt_public_key: read_env_file("JWT_PUBLIC_KEY_FILE")?,
+            jwt_key_id: std::env::var("JWT_KEY_ID").ok(),
+            jwt_previous_keys: {
+                let mut keys = match std::env::var("JWT_PREVIOUS_KEYS") {
+                    Ok(value) => JwtVerificationKey::parse_list(&value, "JWT_PREVIOUS_KEYS")?,
+                    Err(_) => Vec::new(),
+                };
+                if let Ok(value) = std::env::var("JWT_PREVIOUS_PUBLIC_KEYS") {
+                    keys.extend(JwtVerificationKey::parse_public_key_list(
+                        &value,
+                        "JWT_PREVIOUS_PUBLIC_KEYS",
+                    )?);
+                }
+                keys
+            },
+            jwt_ttl_seconds: parse_env("JWT_TTL_SECONDS")?.unwrap_or_else(default_jwt_ttl_seconds),
+            jwt_issuer: std::env::var("JWT_ISSUER").unwrap_or_else(|_| default_jwt_issuer()),
+            jwt_audience: std::env::var("JWT_AUDIENCE").unwrap_or_else(|_| default_jwt_audience()),
+            jwt_leeway_seconds: parse_env("JWT_LEEWAY_SECONDS")?
+                .unwrap_or_else(default_jwt_leeway_seconds),
+            refresh_token_ttl_seconds: parse_env("REFRESH_TOKEN_TTL_SECONDS")?
+                .unwrap_or_else(default_refresh_token_ttl_seconds),
             encryption_key: std::env::var("ENCRYPTION_KEY")
                 .map_err(|_| ConfigError::MissingEnv("ENCRYPTION_KEY"))?,
+            require_pkce: parse_env("REQUIRE_PKCE")?.unwrap_or(false),
+            cookie_sessions: parse_env("COOKIE_SESSIONS")?.unwrap_or(false),
+            cookie_same_site: parse_env("COOKIE_SAME_SITE")?.unwrap_or_default(),
+            allow_query_token: parse_env("ALLOW_QUERY_TOKEN")?.unwrap_or(false),
         };
 
         let server = ServerConfig {
@@ -108,8 +461,34 @@ impl Config {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or_else(default_port),
+            route_prefix: std::env::var("ROUTE_PREFIX")
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|_| default_route_prefix()),
+            cors_allowed_origins: std::env::var("CORS_ALLOWED_ORIGINS")
+                .map(|s| split_list(&s).map(String::from).collect())
+                .unwrap_or_default(),
         };
 
+        if !(server.route_prefix.is_empty() || server.route_prefix.starts_with('/')) {
+            return Err(ConfigError::InvalidEnv("ROUTE_PREFIX"));
+        }
+        if security.jwt_ttl_seconds <= 0 {
+            return Err(ConfigError::InvalidEnv("JWT_TTL_SECONDS"));
+        }
+        if security.refresh_token_ttl_seconds <= 0 {
+            return Err(ConfigError::InvalidEnv("REFRESH_TOKEN_TTL_SECONDS"));
+        }
+
+        if security.jwt_algorithm.is_asymmetric() {
+            if security.jwt_private_key.is_none() {
+                return Err(ConfigError::MissingEnv("JWT_PRIVATE_KEY_FILE"));
+            }
+            if security.jwt_public_key.is_none() {
+                return Err(ConfigError::MissingEnv("JWT_PUBLIC_KEY_FILE"));
+            }
+        }
+        crate::auth::JwtKeyring::from_config(&security).map_err(ConfigError::InvalidJwtKey)?;
+
         Ok(Self {
             discord,
             security,
@@ -118,11 +497,38 @@ impl Config {
     }
 }
 
+/// Parse an optional environment variable, failing if it is set but invalid.
+fn parse_env<T: std::str::FromStr>(var: &'static str) -> Result<Option<T>, ConfigError> {
+    match std::env::var(var) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::InvalidEnv(var)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Read the contents of the file named by an optional environment variable.
+fn read_env_file(var: &'static str) -> Result<Option<String>, ConfigError> {
+    match std::env::var(var) {
+        Ok(path) => std::fs::read_to_string(path)
+            .map(Some)
+            .map_err(|e| ConfigError::ReadFile(var, e)),
+        Err(_) => Ok(None),
+    }
+}
+
 /// Configuration loading errors.
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
     #[error("missing required environment variable: {0}")]
     MissingEnv(&'static str),
+    #[error("invalid value for environment variable: {0}")]
+    InvalidEnv(&'static str),
+    #[error("failed to read file named by environment variable {0}: {1}")]
+    ReadFile(&'static str, std::io::Error),
+    #[error("invalid JWT key: {0}")]
+    InvalidJwtKey(jsonwebtoken::errors::Error),
 }
 
 #[cfg(test)]
@@ -134,6 +540,92 @@ mod tests {
         let config = ServerConfig::default();
         assert_eq!(config.host, "0.0.0.0");
         assert_eq!(config.port, 3000);
+        assert_eq!(config.route_prefix, "/auth");
+        assert!(config.cors_allowed_origins.is_empty());
+        assert_eq!(config.bind_address(), "0.0.0.0:3000");
+    }
+
+    #[test]
+    fn test_discord_config_defaults() {
+        let config: DiscordConfig = serde_json::from_str(
+            r#"{"client_id": "1", "client_secret": "s", "redirect_uri": "r", "bot_token": "b"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.scopes, vec!["identify".to_string()]);
+        assert_eq!(config.post_login_redirect_uri, "/");
+        assert_eq!(
+            config.api_url("/users/@me"),
+            "https://discord.com/api/v10/users/@me"
+        );
+        assert_eq!(config.rate_limit_max_retries, 3);
+        assert_eq!(config.rate_limit_max_wait_seconds, 10);
+        assert!(config.public_key.is_none());
+        assert_eq!(config.entitlement_reconcile_interval_seconds, 3600);
+    }
+
+    #[test]
+    fn test_security_config_jwt_defaults() {
+        let config: SecurityConfig =
+            serde_json::from_str(r#"{"jwt_secret": "s", "encryption_key": "k"}"#).unwrap();
+        assert_eq!(config.jwt_ttl_seconds, 900);
+        assert_eq!(config.jwt_issuer, "catacombs");
+        assert_eq!(config.jwt_audience, "catacombs");
+        assert_eq!(config.jwt_leeway_seconds, 60);
+        assert_eq!(config.refresh_token_ttl_seconds, 30 * 24 * 60 * 60);
+        assert!(!config.cookie_sessions);
+        assert_eq!(config.cookie_same_site, CookieSameSite::Lax);
+    }
+
+    #[test]
+    fn test_cookie_same_site_from_str() {
+        assert_eq!("Strict".parse(), Ok(CookieSameSite::Strict));
+        assert_eq!("None".parse(), Ok(CookieSameSite::None));
+        assert!("strict-ish".parse::<CookieSameSite>().is_err());
+    }
+
+    #[test]
+    fn test_jwt_verification_key_parse_list() {
+        let keys = JwtVerificationKey::parse_list("old-1:secret1, old-2:c2VjcmV0Mg==", "TEST_VAR")
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].kid, "old-1");
+        assert_eq!(keys[0].key, "secret1");
+        assert_eq!(keys[0].algorithm, JwtAlgorithm::HS256);
+        assert_eq!(keys[1].kid, "old-2");
+        assert_eq!(keys[1].key, "c2VjcmV0Mg==");
+
+        assert!(JwtVerificationKey::parse_list("", "TEST_VAR")
+            .unwrap()
+            .is_empty());
+        assert!(JwtVerificationKey::parse_list("no-separator", "TEST_VAR").is_err());
+    }
+
+    #[test]
+    fn test_jwt_verification_key_parse_public_key_list() {
+        let path = std::env::temp_dir().join("catacombs-test-public-key.pem");
+        std::fs::write(&path, "-----BEGIN PUBLIC KEY-----").unwrap();
+
+        let keys = JwtVerificationKey::parse_public_key_list(
+            &format!("old-ed:EdDSA:{}", path.display()),
+            "TEST_VAR",
+        )
+        .unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].kid, "old-ed");
+        assert_eq!(keys[0].algorithm, JwtAlgorithm::EdDSA);
+        assert_eq!(keys[0].key, "-----BEGIN PUBLIC KEY-----");
+
+        assert!(JwtVerificationKey::parse_public_key_list("kid:XX256:/tmp/x", "TEST_VAR").is_err());
+        assert!(JwtVerificationKey::parse_public_key_list("kid:EdDSA", "TEST_VAR").is_err());
+    }
+
+    #[test]
+    fn test_jwt_algorithm_from_str() {
+        assert_eq!("HS256".parse(), Ok(JwtAlgorithm::HS256));
+        assert_eq!("EdDSA".parse(), Ok(JwtAlgorithm::EdDSA));
+        assert!("none".parse::<JwtAlgorithm>().is_err());
+        assert!(!JwtAlgorithm::HS256.is_asymmetric());
+        assert!(JwtAlgorithm::ES256.is_asymmetric());
     }
 
     #[test]
@@ -0,0 +1,201 @@
+//! Cookie-based session delivery with double-submit CSRF protection.
+//!
+//! When `SecurityConfig::cookie_sessions` is enabled, `/exchange`, `/callback`
+//! and `/refresh` put the JWT and refresh token in `HttpOnly`, `Secure` cookies
+//! instead of the response body, and the `AuthenticatedUser` extractor reads
+//! the JWT from its cookie. Browsers attach cookies to cross-site requests, so
+//! unsafe requests authenticated by cookie must also echo the value of the
+//! readable [`CSRF_COOKIE`] in the [`CSRF_HEADER`] header.
+
+use axum::http::{HeaderMap, Method};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use rand::RngCore;
+use subtle::ConstantTimeEq;
+
+use crate::config::{CookieSameSite, SecurityConfig};
+
+/// Cookie holding the JWT.
+pub const ACCESS_TOKEN_COOKIE: &str = "catacombs_access_token";
+
+/// Cookie holding the first-party refresh token.
+pub const REFRESH_TOKEN_COOKIE: &str = "catacombs_refresh_token";
+
+/// Cookie holding the CSRF token. Unlike the token cookies it is readable by
+/// JavaScript, so the front-end can copy it into [`CSRF_HEADER`].
+pub const CSRF_COOKIE: &str = "catacombs_csrf";
+
+/// Header that must repeat the [`CSRF_COOKIE`] value on unsafe requests.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Generate a random CSRF token (256 bits, base64url-encoded).
+#[must_use]
+pub fn generate_csrf_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    BASE64_URL.encode(bytes)
+}
+
+/// Add the JWT, refresh token and CSRF cookies to a jar.
+///
+/// The JWT cookie lives as long as the JWT; the refresh token and CSRF cookies
+/// live as long as the refresh token.
+pub fn set_session_cookies(
+    jar: CookieJar,
+    access_token: &str,
+    refresh_token: &str,
+    csrf_token: &str,
+    security: &SecurityConfig,
+) -> CookieJar {
+    let session_lifetime = time::Duration::seconds(security.refresh_token_ttl_seconds);
+
+    jar.add(session_cookie(
+        ACCESS_TOKEN_COOKIE,
+        access_token,
+        time::Duration::seconds(security.jwt_ttl_seconds),
+        true,
+        security,
+    ))
+    .add(session_cookie(
+        REFRESH_TOKEN_COOKIE,
+        refresh_token,
+        session_lifetime,
+        true,
+        security,
+    ))
+    .add(session_cookie(
+        CSRF_COOKIE,
+        csrf_token,
+        session_lifetime,
+        false,
+        security,
+    ))
+}
+
+/// Remove the session cookies from a jar.
+///
+/// Does nothing unless cookie sessions are enabled, so logout routes can call
+/// it unconditionally.
+pub fn clear_session_cookies(jar: CookieJar, security: &SecurityConfig) -> CookieJar {
+    if !security.cookie_sessions {
+        return jar;
+    }
+
+    [ACCESS_TOKEN_COOKIE, REFRESH_TOKEN_COOKIE, CSRF_COOKIE]
+        .into_iter()
+        .fold(jar, |jar, name| jar.remove(Cookie::build(name).path("/")))
+}
+
+/// Returns true if the request's CSRF header matches its CSRF cookie.
+#[must_use]
+pub fn verify_csrf(jar: &CookieJar, headers: &HeaderMap) -> bool {
+    let Some(cookie) = jar.get(CSRF_COOKIE) else {
+        return false;
+    };
+    let Some(header) = headers.get(CSRF_HEADER) else {
+        return false;
+    };
+
+    !cookie.value().is_empty() && bool::from(cookie.value().as_bytes().ct_eq(header.as_bytes()))
+}
+
+/// Returns true for methods that may change state and therefore need CSRF protection.
+#[must_use]
+pub fn is_unsafe_method(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn session_cookie(
+    name: &'static str,
+    value: &str,
+    max_age: time::Duration,
+    http_only: bool,
+    security: &SecurityConfig,
+) -> Cookie<'static> {
+    Cookie::build((name, value.to_string()))
+        .path("/")
+        .http_only(http_only)
+        .secure(true)
+        .same_site(match security.cookie_same_site {
+            CookieSameSite::Strict => SameSite::Strict,
+            CookieSameSite::Lax => SameSite::Lax,
+            CookieSameSite::None => SameSite::None,
+        })
+        .max_age(max_age)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn cookie_security() -> SecurityConfig {
+        serde_json::from_value(serde_json::json!({
+            "jwt_secret": "secret",
+            "encryption_key": "",
+            "cookie_sessions": true,
+            "cookie_same_site": "Strict",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_set_session_cookies() {
+        let security = cookie_security();
+        let jar = set_session_cookies(CookieJar::new(), "jwt", "refresh", "csrf", &security);
+
+        let access = jar.get(ACCESS_TOKEN_COOKIE).unwrap();
+        assert_eq!(access.value(), "jwt");
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(true));
+        assert_eq!(access.same_site(), Some(SameSite::Strict));
+        assert_eq!(access.max_age(), Some(time::Duration::seconds(900)));
+
+        assert_eq!(
+            jar.get(REFRESH_TOKEN_COOKIE).unwrap().http_only(),
+            Some(true)
+        );
+        let csrf = jar.get(CSRF_COOKIE).unwrap();
+        assert_eq!(csrf.value(), "csrf");
+        assert_eq!(csrf.http_only(), Some(false));
+    }
+
+    #[test]
+    fn test_clear_session_cookies_only_in_cookie_mode() {
+        let mut security = cookie_security();
+        let jar = set_session_cookies(CookieJar::new(), "jwt", "refresh", "csrf", &security);
+
+        security.cookie_sessions = false;
+        let kept = clear_session_cookies(jar.clone(), &security);
+        assert!(kept.get(ACCESS_TOKEN_COOKIE).is_some());
+
+        security.cookie_sessions = true;
+        let cleared = clear_session_cookies(jar, &security);
+        assert!(cleared.get(ACCESS_TOKEN_COOKIE).is_none());
+        assert!(cleared.get(CSRF_COOKIE).is_none());
+    }
+
+    #[test]
+    fn test_verify_csrf() {
+        let jar = CookieJar::new().add(Cookie::new(CSRF_COOKIE, "token-123"));
+        let mut headers = HeaderMap::new();
+        assert!(!verify_csrf(&jar, &headers), "missing header");
+
+        headers.insert(CSRF_HEADER, HeaderValue::from_static("token-124"));
+        assert!(!verify_csrf(&jar, &headers), "mismatched header");
+
+        headers.insert(CSRF_HEADER, HeaderValue::from_static("token-123"));
+        assert!(verify_csrf(&jar, &headers));
+        assert!(!verify_csrf(&CookieJar::new(), &headers), "missing cookie");
+    }
+
+    #[test]
+    fn test_unsafe_methods() {
+        assert!(is_unsafe_method(&Method::POST));
+        assert!(is_unsafe_method(&Method::DELETE));
+        assert!(!is_unsafe_method(&Method::GET));
+        assert!(!is_unsafe_method(&Method::HEAD));
+    }
+}
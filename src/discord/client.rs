@@ -0,0 +1,319 @@
+//! `reqwest` implementation of [`DiscordApi`].
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use super::{
+    rate_limit::{backoff, RateLimitHeaders, RateLimiter},
+    DiscordApi, DiscordEntitlement, DiscordError, DiscordResult, DiscordTokenResponse, DiscordUser,
+};
+use crate::config::DiscordConfig;
+
+/// Calls the Discord API at `DiscordConfig::api_base_url` over `reqwest`.
+///
+/// Token endpoints authenticate with the client ID and secret, entitlements
+/// with the bot token. Clones share rate limit state.
+#[derive(Clone)]
+pub struct DiscordClient {
+    config: DiscordConfig,
+    http_client: reqwest::Client,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl DiscordClient {
+    /// Create a client for the given application.
+    #[must_use]
+    pub fn new(config: DiscordConfig, http_client: reqwest::Client) -> Self {
+        Self {
+            config,
+            http_client,
+            rate_limiter: Arc::new(RateLimiter::default()),
+        }
+    }
+
+    /// Send a request, waiting out known rate limits and retrying 429s.
+    ///
+    /// `route` names the rate limit bucket the request draws from, or is `None`
+    /// for requests limited per user token. `request` builds a fresh request
+    /// for every attempt.
+    async fn send(
+        &self,
+        route: Option<&str>,
+        request: impl Fn() -> reqwest::RequestBuilder + Send + Sync,
+    ) -> DiscordResult<reqwest::Response> {
+        let max_wait = Duration::from_secs(self.config.rate_limit_max_wait_seconds);
+        let mut attempt = 0;
+
+        loop {
+            let wait = self.rate_limiter.acquire(route);
+            if wait > max_wait {
+                return Err(DiscordError::RateLimited {
+                    retry_after: wait,
+                    global: false,
+                });
+            }
+            if !wait.is_zero() {
+                tracing::debug!("Waiting {:?} for Discord rate limit on {:?}", wait, route);
+                tokio::time::sleep(wait).await;
+            }
+
+            let response = request().send().await?;
+            let headers = RateLimitHeaders::parse(response.headers());
+            self.rate_limiter.update(route, &headers);
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                return check_status(response).await;
+            }
+
+            let retry_after = headers.retry_after.or(headers.reset_after);
+            let delay = backoff(attempt, retry_after);
+            if attempt >= self.config.rate_limit_max_retries || delay > max_wait {
+                tracing::warn!(
+                    "Giving up on Discord request to {:?} after {} rate limited attempts",
+                    route,
+                    attempt + 1
+                );
+                return Err(DiscordError::RateLimited {
+                    retry_after: retry_after.unwrap_or(delay),
+                    global: headers.global,
+                });
+            }
+
+            tracing::warn!(
+                "Discord rate limited request to {:?} (global: {}), retrying in {:?}",
+                route,
+                headers.global,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// List the application's entitlements with the bot token.
+    async fn get_entitlements(&self, query: &str) -> DiscordResult<Vec<DiscordEntitlement>> {
+        let url = self.config.api_url(&format!(
+            "/applications/{}/entitlements?{}",
+            self.config.client_id, query
+        ));
+        let response = self
+            .send(Some("GET /applications/{id}/entitlements"), || {
+                self.http_client
+                    .get(&url)
+                    .header("Authorization", format!("Bot {}", self.config.bot_token))
+            })
+            .await?;
+        decode(response).await
+    }
+
+    /// POST a form to an `OAuth2` endpoint with client credentials.
+    async fn post_form(
+        &self,
+        route: &str,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> DiscordResult<reqwest::Response> {
+        let url = self.config.api_url(path);
+        self.send(Some(route), || {
+            self.http_client
+                .post(&url)
+                .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+                .form(params)
+        })
+        .await
+    }
+}
+
+/// Turn a non-success response into `DiscordError::Status`.
+async fn check_status(response: reqwest::Response) -> DiscordResult<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await?;
+    Err(DiscordError::Status { status, body })
+}
+
+/// Decode a JSON response body.
+async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> DiscordResult<T> {
+    let body = response.bytes().await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+#[async_trait]
+impl DiscordApi for DiscordClient {
+    async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: Option<&str>,
+    ) -> DiscordResult<DiscordTokenResponse> {
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+        ];
+        if let Some(code_verifier) = code_verifier {
+            params.push(("code_verifier", code_verifier));
+        }
+
+        let response = self
+            .post_form("POST /oauth2/token", "/oauth2/token", &params)
+            .await?;
+        decode(response).await
+    }
+
+    async fn refresh_token(&self, refresh_token: &str) -> DiscordResult<DiscordTokenResponse> {
+        let params = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ];
+        let response = self
+            .post_form("POST /oauth2/token", "/oauth2/token", &params)
+            .await?;
+        decode(response).await
+    }
+
+    async fn revoke_token(&self, token: &str) -> DiscordResult<()> {
+        self.post_form(
+            "POST /oauth2/token/revoke",
+            "/oauth2/token/revoke",
+            &[("token", token)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn current_user(&self, access_token: &str) -> DiscordResult<DiscordUser> {
+        // Limited per access token, so there is no shared bucket to track
+        let url = self.config.api_url("/users/@me");
+        let response = self
+            .send(None, || {
+                self.http_client.get(&url).bearer_auth(access_token)
+            })
+            .await?;
+        decode(response).await
+    }
+
+    async fn user_entitlements(
+        &self,
+        user_id: i64,
+        after: i64,
+        limit: u8,
+    ) -> DiscordResult<Vec<DiscordEntitlement>> {
+        self.get_entitlements(&format!(
+            "user_id={user_id}&after={after}&limit={limit}&exclude_ended=false"
+        ))
+        .await
+    }
+
+    async fn list_entitlements(
+        &self,
+        after: i64,
+        limit: u8,
+    ) -> DiscordResult<Vec<DiscordEntitlement>> {
+        self.get_entitlements(&format!(
+            "after={after}&limit={limit}&exclude_ended=false&exclude_deleted=false"
+        ))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::testing::{mock_entitlement, MockDiscord, MockEndpoint, MockResponse};
+
+    async fn client() -> (MockDiscord, DiscordClient) {
+        let discord = MockDiscord::start().await.unwrap();
+        let client = DiscordClient::new(discord.config().discord, reqwest::Client::new());
+        (discord, client)
+    }
+
+    #[tokio::test]
+    async fn test_discord_client_decodes_responses() {
+        let (discord, client) = client().await;
+        discord.respond(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(1, 42, 777)]),
+        );
+
+        let token = client.exchange_code("abc", Some("verifier")).await.unwrap();
+        assert_eq!(token.granted_scopes(), vec!["identify", "email"]);
+        let user = client.current_user(&token.access_token).await.unwrap();
+        assert_eq!(user.username, "mock_user");
+        let entitlements = client.user_entitlements(42, 0, 100).await.unwrap();
+        assert_eq!(entitlements[0].sku_id, "777");
+        client.revoke_token(&token.refresh_token).await.unwrap();
+
+        let exchange = &discord.requests_to(MockEndpoint::Token)[0];
+        assert_eq!(
+            exchange.form_value("code_verifier").as_deref(),
+            Some("verifier")
+        );
+        let me = &discord.requests_to(MockEndpoint::CurrentUser)[0];
+        assert_eq!(
+            me.authorization.as_deref(),
+            Some("Bearer mock_access_token")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discord_client_returns_typed_errors() {
+        let (discord, client) = client().await;
+        discord.enqueue(
+            MockEndpoint::Token,
+            MockResponse::error(StatusCode::UNAUTHORIZED),
+        );
+        discord.enqueue(
+            MockEndpoint::CurrentUser,
+            MockResponse::json(serde_json::json!({"id": 1})),
+        );
+
+        let err = client.refresh_token("stale").await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::UNAUTHORIZED));
+        assert!(matches!(err, DiscordError::Status { .. }));
+
+        let err = client.current_user("token").await.unwrap_err();
+        assert!(matches!(err, DiscordError::Decode(_)));
+    }
+
+    #[tokio::test]
+    async fn test_discord_client_retries_rate_limited_requests() {
+        let (discord, client) = client().await;
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::error(StatusCode::TOO_MANY_REQUESTS)
+                .with_header("retry-after", "0.05")
+                .with_header("x-ratelimit-bucket", "entitlements"),
+        );
+
+        let entitlements = client.user_entitlements(42, 0, 100).await.unwrap();
+        assert!(entitlements.is_empty());
+        assert_eq!(discord.requests_to(MockEndpoint::Entitlements).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_discord_client_gives_up_on_rate_limits() {
+        let discord = MockDiscord::start().await.unwrap();
+        let mut config = discord.config().discord;
+        config.rate_limit_max_retries = 1;
+        let client = DiscordClient::new(config, reqwest::Client::new());
+        let rate_limited = MockResponse::error(StatusCode::TOO_MANY_REQUESTS)
+            .with_header("retry-after", "0.05")
+            .with_header("x-ratelimit-global", "true");
+        discord.enqueue(MockEndpoint::Token, rate_limited.clone());
+        discord.enqueue(MockEndpoint::Token, rate_limited);
+
+        let err = client.exchange_code("abc", None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            DiscordError::RateLimited { global: true, .. }
+        ));
+        assert_eq!(err.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(discord.requests_to(MockEndpoint::Token).len(), 2);
+    }
+}
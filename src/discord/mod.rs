@@ -0,0 +1,160 @@
+//! Discord REST API client.
+//!
+//! [`DiscordApi`] covers every Discord call catacombs makes: `OAuth2` token
+//! exchange, refresh and revocation, `users/@me` and entitlements.
+//! [`DiscordClient`] implements it over `reqwest`; `AppState` holds one as a
+//! trait object, so tests and host apps can swap in their own implementation
+//! with `AppState::with_discord_api`.
+//!
+//! `DiscordClient` waits out Discord's rate limits before sending and retries
+//! 429s with jittered backoff, up to `DiscordConfig::rate_limit_max_retries`
+//! times, before failing with [`DiscordError::RateLimited`].
+//!
+//! [`verify_signature`] checks the Ed25519 signature of webhook events and
+//! interactions Discord sends to catacombs.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+
+mod client;
+mod models;
+mod rate_limit;
+mod signature;
+
+pub use client::DiscordClient;
+pub use models::{DiscordEntitlement, DiscordTokenResponse, DiscordUser};
+pub use signature::{
+    parse_public_key, verify_signature, SignatureError, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+
+/// Result type for Discord API calls.
+pub type DiscordResult<T> = std::result::Result<T, DiscordError>;
+
+/// Most entitlements Discord returns in one page.
+pub const ENTITLEMENT_PAGE_LIMIT: u8 = 100;
+
+/// Error returned by a Discord API call.
+#[derive(Debug, thiserror::Error)]
+pub enum DiscordError {
+    /// The request could not be sent or its response could not be read.
+    #[error("request to Discord failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    /// Discord answered with a non-success status.
+    #[error("Discord returned {status}: {body}")]
+    Status {
+        /// HTTP status code.
+        status: StatusCode,
+        /// Response body, usually a JSON `{"message", "code"}` object.
+        body: String,
+    },
+
+    /// Discord kept rate limiting the request and the client gave up.
+    #[error("rate limited by Discord, retry after {retry_after:?}")]
+    RateLimited {
+        /// How long Discord asked to wait.
+        retry_after: Duration,
+        /// Whether the limit applies to every request, not just this route.
+        global: bool,
+    },
+
+    /// The response body did not match the expected model.
+    #[error("failed to decode Discord response: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+impl DiscordError {
+    /// The HTTP status Discord answered with, if it answered.
+    #[must_use]
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Status { status, .. } => Some(*status),
+            Self::Request(e) => e.status(),
+            Self::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Self::Decode(_) => None,
+        }
+    }
+}
+
+/// The Discord API calls catacombs makes.
+#[async_trait]
+pub trait DiscordApi: Send + Sync {
+    /// Exchange an `OAuth2` authorization code for a token.
+    ///
+    /// Parameters:
+    ///     - code: `&str` - Authorization code from Discord's redirect
+    ///     - `code_verifier`: `Option<&str>` - PKCE code verifier, if the request used PKCE
+    /// Returns:
+    ///     - `DiscordResult<DiscordTokenResponse>` - The new token grant
+    /// Errors:
+    ///     - `DiscordError` - If the request fails or Discord rejects the code
+    async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: Option<&str>,
+    ) -> DiscordResult<DiscordTokenResponse>;
+
+    /// Exchange a Discord refresh token for a new token.
+    ///
+    /// Parameters:
+    ///     - `refresh_token`: `&str` - Discord refresh token
+    /// Returns:
+    ///     - `DiscordResult<DiscordTokenResponse>` - The renewed token grant
+    /// Errors:
+    ///     - `DiscordError` - If the request fails or Discord rejects the token
+    async fn refresh_token(&self, refresh_token: &str) -> DiscordResult<DiscordTokenResponse>;
+
+    /// Revoke a Discord access or refresh token.
+    ///
+    /// Parameters:
+    ///     - token: `&str` - Token to revoke
+    /// Errors:
+    ///     - `DiscordError` - If the request fails or Discord answers with an error
+    async fn revoke_token(&self, token: &str) -> DiscordResult<()>;
+
+    /// Fetch the user a Discord access token belongs to.
+    ///
+    /// Parameters:
+    ///     - `access_token`: `&str` - Discord `OAuth2` access token
+    /// Returns:
+    ///     - `DiscordResult<DiscordUser>` - The token's user
+    /// Errors:
+    ///     - `DiscordError` - If the request fails or Discord rejects the token
+    async fn current_user(&self, access_token: &str) -> DiscordResult<DiscordUser>;
+
+    /// Fetch a page of a user's entitlements to this application, including
+    /// ended ones, in ascending ID order.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    ///     - after: `i64` - Only return entitlements with a greater ID; 0 for the first page
+    ///     - limit: `u8` - Page size, at most `ENTITLEMENT_PAGE_LIMIT`
+    /// Returns:
+    ///     - `DiscordResult<Vec<DiscordEntitlement>>` - The page, empty past the last entitlement
+    /// Errors:
+    ///     - `DiscordError` - If the request fails or Discord answers with an error
+    async fn user_entitlements(
+        &self,
+        user_id: i64,
+        after: i64,
+        limit: u8,
+    ) -> DiscordResult<Vec<DiscordEntitlement>>;
+
+    /// Fetch a page of every entitlement to this application, including ended
+    /// and deleted ones, in ascending ID order.
+    ///
+    /// Parameters:
+    ///     - after: `i64` - Only return entitlements with a greater ID; 0 for the first page
+    ///     - limit: `u8` - Page size, at most `ENTITLEMENT_PAGE_LIMIT`
+    /// Returns:
+    ///     - `DiscordResult<Vec<DiscordEntitlement>>` - The page, empty past the last entitlement
+    /// Errors:
+    ///     - `DiscordError` - If the request fails or Discord answers with an error
+    async fn list_entitlements(
+        &self,
+        after: i64,
+        limit: u8,
+    ) -> DiscordResult<Vec<DiscordEntitlement>>;
+}
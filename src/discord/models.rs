@@ -0,0 +1,57 @@
+//! Discord API response models.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Discord user from the `/users/@me` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscordUser {
+    /// Snowflake user ID.
+    pub id: String,
+    pub username: String,
+    /// Avatar hash; `a_`-prefixed hashes are animated.
+    pub avatar: Option<String>,
+    pub global_name: Option<String>,
+    /// Legacy discriminator, `"0"` for migrated usernames.
+    pub discriminator: Option<String>,
+}
+
+/// Discord `OAuth2` token response, for both code exchange and refresh.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscordTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    /// Lifetime of `access_token`, in seconds.
+    pub expires_in: i64,
+    pub refresh_token: String,
+    /// Space-separated granted scopes.
+    pub scope: String,
+}
+
+impl DiscordTokenResponse {
+    /// Scopes granted by the user, parsed from the space-separated `scope` field.
+    #[must_use]
+    pub fn granted_scopes(&self) -> Vec<String> {
+        self.scope.split_whitespace().map(String::from).collect()
+    }
+}
+
+/// Discord entitlement from the `/applications/{id}/entitlements` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscordEntitlement {
+    /// Snowflake entitlement ID.
+    pub id: String,
+    /// Snowflake SKU ID.
+    pub sku_id: String,
+    /// Snowflake ID of the user granted the entitlement, if user-scoped.
+    pub user_id: Option<String>,
+    /// Entitlement type (8 is an application subscription).
+    #[serde(rename = "type")]
+    pub entitlement_type: i32,
+    #[serde(default)]
+    pub deleted: bool,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub consumed: bool,
+}
@@ -0,0 +1,224 @@
+//! Discord rate limit tracking.
+//!
+//! Discord reports per-route limits in `X-RateLimit-*` headers: requests to
+//! routes sharing an `X-RateLimit-Bucket` draw from the same
+//! `X-RateLimit-Remaining` until `X-RateLimit-Reset-After` elapses. A 429
+//! carries `Retry-After`, and `X-RateLimit-Global` when every request made
+//! with the bot token is blocked.
+//!
+//! [`RateLimiter`] remembers each route's bucket and the global block, so a
+//! request waits before it is sent instead of spending a 429.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use reqwest::header::HeaderMap;
+
+/// Backoff before the first retry when a 429 carries no `Retry-After`.
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+
+/// What Discord said about a route in one response.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RateLimitHeaders {
+    /// `X-RateLimit-Bucket`.
+    pub bucket: Option<String>,
+    /// `X-RateLimit-Remaining`.
+    pub remaining: Option<u32>,
+    /// `X-RateLimit-Reset-After`.
+    pub reset_after: Option<Duration>,
+    /// `Retry-After`, only sent with 429s.
+    pub retry_after: Option<Duration>,
+    /// `X-RateLimit-Global`, or an `X-RateLimit-Scope` of `global`.
+    pub global: bool,
+}
+
+impl RateLimitHeaders {
+    /// Read the rate limit headers of a response.
+    pub fn parse(headers: &HeaderMap) -> Self {
+        let value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let seconds = |name: &str| {
+            value(name)
+                .and_then(|v| v.parse::<f64>().ok())
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        };
+
+        Self {
+            bucket: value("x-ratelimit-bucket").map(String::from),
+            remaining: value("x-ratelimit-remaining").and_then(|v| v.parse().ok()),
+            reset_after: seconds("x-ratelimit-reset-after"),
+            retry_after: seconds("retry-after"),
+            global: value("x-ratelimit-global").is_some_and(|v| v.eq_ignore_ascii_case("true"))
+                || value("x-ratelimit-scope") == Some("global"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    remaining: u32,
+    reset_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct RateLimitState {
+    /// Bucket hash of each route, learned from `X-RateLimit-Bucket`.
+    routes: HashMap<String, String>,
+    buckets: HashMap<String, Bucket>,
+    global_reset_at: Option<Instant>,
+}
+
+/// Per-route buckets and the global limit, shared by every request of a client.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    state: Mutex<RateLimitState>,
+}
+
+impl RateLimiter {
+    /// Reserve a request on a route, returning how long to wait before sending it.
+    ///
+    /// `route` is `None` for requests limited per user token rather than per
+    /// application, which only honour the global limit.
+    pub fn acquire(&self, route: Option<&str>) -> Duration {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        let mut wait = state.global_reset_at.map_or(Duration::ZERO, |reset_at| {
+            reset_at.saturating_duration_since(now)
+        });
+
+        let Some(hash) = route.and_then(|route| state.routes.get(route).cloned()) else {
+            return wait;
+        };
+        if let Some(bucket) = state.buckets.get_mut(&hash) {
+            if bucket.reset_at <= now {
+                state.buckets.remove(&hash);
+            } else if bucket.remaining == 0 {
+                wait = wait.max(bucket.reset_at - now);
+            } else {
+                bucket.remaining -= 1;
+            }
+        }
+        wait
+    }
+
+    /// Record the limits a response reported for a route.
+    pub fn update(&self, route: Option<&str>, headers: &RateLimitHeaders) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+
+        if headers.global {
+            if let Some(retry_after) = headers.retry_after {
+                let reset_at = now + retry_after;
+                state.global_reset_at =
+                    Some(state.global_reset_at.map_or(reset_at, |r| r.max(reset_at)));
+            }
+            return;
+        }
+
+        let (Some(route), Some(hash)) = (route, headers.bucket.as_ref()) else {
+            return;
+        };
+        state.routes.insert(route.to_string(), hash.clone());
+        if let (Some(remaining), Some(reset_after)) = (headers.remaining, headers.reset_after) {
+            // A 429 means the bucket is empty whatever it claims.
+            let reset_after = headers
+                .retry_after
+                .map_or(reset_after, |r| r.max(reset_after));
+            let remaining = if headers.retry_after.is_some() {
+                0
+            } else {
+                remaining
+            };
+            state.buckets.insert(
+                hash.clone(),
+                Bucket {
+                    remaining,
+                    reset_at: now + reset_after,
+                },
+            );
+        }
+    }
+}
+
+/// How long to wait before retry number `attempt` (starting at 0) after a 429.
+///
+/// Waits at least `retry_after`, or an exponential backoff when Discord did not
+/// say, plus up to a quarter again of random jitter so that requests limited
+/// together do not retry together.
+pub(crate) fn backoff(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let base = retry_after.unwrap_or_else(|| BASE_BACKOFF * 2u32.saturating_pow(attempt.min(16)));
+    base + base.mul_f64(rand::random::<f64>() / 4.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> RateLimitHeaders {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        RateLimitHeaders::parse(&map)
+    }
+
+    #[test]
+    fn test_rate_limit_headers_parse() {
+        let parsed = headers(&[
+            ("x-ratelimit-bucket", "abcd"),
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset-after", "1.5"),
+            ("retry-after", "2"),
+            ("x-ratelimit-scope", "global"),
+        ]);
+        assert_eq!(parsed.bucket.as_deref(), Some("abcd"));
+        assert_eq!(parsed.remaining, Some(0));
+        assert_eq!(parsed.reset_after, Some(Duration::from_millis(1500)));
+        assert_eq!(parsed.retry_after, Some(Duration::from_secs(2)));
+        assert!(parsed.global);
+
+        assert_eq!(headers(&[]), RateLimitHeaders::default());
+    }
+
+    #[test]
+    fn test_rate_limiter_waits_for_exhausted_bucket() {
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.acquire(Some("GET /entitlements")), Duration::ZERO);
+
+        limiter.update(
+            Some("GET /entitlements"),
+            &headers(&[
+                ("x-ratelimit-bucket", "abcd"),
+                ("x-ratelimit-remaining", "1"),
+                ("x-ratelimit-reset-after", "60"),
+            ]),
+        );
+        // The last request in the bucket goes through, the next one waits.
+        assert_eq!(limiter.acquire(Some("GET /entitlements")), Duration::ZERO);
+        assert!(limiter.acquire(Some("GET /entitlements")) > Duration::from_secs(59));
+        assert_eq!(limiter.acquire(Some("POST /oauth2/token")), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_rate_limiter_global_limit_blocks_every_route() {
+        let limiter = RateLimiter::default();
+        limiter.update(
+            Some("POST /oauth2/token"),
+            &headers(&[("retry-after", "30"), ("x-ratelimit-global", "true")]),
+        );
+        assert!(limiter.acquire(Some("GET /entitlements")) > Duration::from_secs(29));
+        assert!(limiter.acquire(None) > Duration::from_secs(29));
+    }
+
+    #[test]
+    fn test_backoff_is_jittered_and_honours_retry_after() {
+        let wait = backoff(0, Some(Duration::from_secs(2)));
+        assert!(wait >= Duration::from_secs(2) && wait <= Duration::from_millis(2500));
+        let wait = backoff(2, None);
+        assert!(wait >= Duration::from_secs(2) && wait <= Duration::from_millis(2500));
+    }
+}
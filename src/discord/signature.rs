@@ -0,0 +1,120 @@
+//! Verification of requests Discord signs.
+//!
+//! Discord signs every webhook event and interaction it sends with the
+//! application's Ed25519 key: `X-Signature-Ed25519` is the hex signature of
+//! the `X-Signature-Timestamp` header followed by the raw request body.
+//! Discord disables endpoints that accept unsigned or forged requests.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use reqwest::header::HeaderMap;
+
+/// Header carrying the hex-encoded Ed25519 signature.
+pub const SIGNATURE_HEADER: &str = "x-signature-ed25519";
+/// Header carrying the timestamp the signature covers.
+pub const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+
+/// Why a signed request was rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    /// A signature header is missing or not valid UTF-8.
+    #[error("missing {0} header")]
+    MissingHeader(&'static str),
+
+    /// The configured public key is not a hex-encoded Ed25519 key.
+    #[error("invalid public key")]
+    InvalidKey,
+
+    /// The signature is malformed or does not match the request.
+    #[error("invalid signature")]
+    InvalidSignature,
+}
+
+/// Parse a hex-encoded Ed25519 public key, as shown in the Developer Portal.
+///
+/// # Errors
+///    - `SignatureError::InvalidKey` if `public_key` is not a valid key.
+pub fn parse_public_key(public_key: &str) -> Result<VerifyingKey, SignatureError> {
+    let bytes: [u8; 32] = hex::decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(SignatureError::InvalidKey)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| SignatureError::InvalidKey)
+}
+
+/// Verify that Discord signed a request with the application's key.
+///
+/// Parameters:
+///     - `public_key`: `&str` - Hex-encoded application public key
+///     - headers: `&HeaderMap` - Request headers
+///     - body: `&[u8]` - Raw request body
+/// Errors:
+///     - `SignatureError` - If a header is missing, the key is invalid or the signature does not match
+pub fn verify_signature(
+    public_key: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), SignatureError> {
+    let header = |name: &'static str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(SignatureError::MissingHeader(name))
+    };
+    let signature = header(SIGNATURE_HEADER)?;
+    let timestamp = header(TIMESTAMP_HEADER)?;
+
+    let key = parse_public_key(public_key)?;
+    let signature: [u8; 64] = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(SignatureError::InvalidSignature)?;
+
+    let mut message = Vec::with_capacity(timestamp.len() + body.len());
+    message.extend_from_slice(timestamp.as_bytes());
+    message.extend_from_slice(body);
+    key.verify_strict(&message, &Signature::from_bytes(&signature))
+        .map_err(|_| SignatureError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn signed_headers(key: &SigningKey, timestamp: &str, body: &[u8]) -> HeaderMap {
+        let message = [timestamp.as_bytes(), body].concat();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&hex::encode(key.sign(&message).to_bytes())).unwrap(),
+        );
+        headers.insert(TIMESTAMP_HEADER, HeaderValue::from_str(timestamp).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let public_key = hex::encode(key.verifying_key().as_bytes());
+        let headers = signed_headers(&key, "1700000000", br#"{"type":0}"#);
+
+        assert!(verify_signature(&public_key, &headers, br#"{"type":0}"#).is_ok());
+        assert!(matches!(
+            verify_signature(&public_key, &headers, br#"{"type":1}"#),
+            Err(SignatureError::InvalidSignature)
+        ));
+
+        let other = hex::encode(SigningKey::from_bytes(&[2; 32]).verifying_key().as_bytes());
+        assert!(verify_signature(&other, &headers, br#"{"type":0}"#).is_err());
+        assert!(matches!(
+            verify_signature("not hex", &headers, br#"{"type":0}"#),
+            Err(SignatureError::InvalidKey)
+        ));
+        assert!(matches!(
+            verify_signature(&public_key, &HeaderMap::new(), b""),
+            Err(SignatureError::MissingHeader(SIGNATURE_HEADER))
+        ));
+    }
+}
@@ -0,0 +1,612 @@
+//! Discord entitlement syncing.
+//!
+//! Fetches a user's entitlements from Discord, stores them and derives the
+//! user's subscription tier from `DiscordConfig::premium_sku_id`. Logins sync
+//! the user automatically; `sync_user_entitlements` does the same on demand,
+//! and `apply_entitlement` handles one entitlement pushed by Discord.
+//!
+//! `reconcile_subscription` applies the tier rules to the stored entitlements:
+//! an active entitlement for the premium SKU grants a Discord subscription, and
+//! a Discord subscription with none left is downgraded to free. `Manual` and
+//! `External` grants are never changed. Every decision that touches a
+//! subscription is recorded with `EntitlementStorage::record_subscription_decision`.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    discord::{DiscordEntitlement, ENTITLEMENT_PAGE_LIMIT},
+    models::{
+        Entitlement, EntitlementUpsertParams, SubscriptionAction, SubscriptionDecision,
+        SubscriptionSource, SubscriptionTier, User,
+    },
+    AppState,
+};
+
+/// Fetch a user's entitlements from Discord, store them and update their tier.
+///
+/// Returns the user's tier after reconciliation, or the tier the entitlements
+/// grant if the user is not stored.
+///
+/// # Errors
+///    - Returns an error if the Discord request or a storage write fails. A
+///      failed request leaves the stored entitlements and tier untouched.
+pub async fn sync_user_entitlements(
+    state: &AppState,
+    user_id: i64,
+) -> anyhow::Result<SubscriptionTier> {
+    let mut entitlements = Vec::new();
+    let mut after = 0;
+    let complete = loop {
+        let page = state
+            .discord
+            .user_entitlements(user_id, after, ENTITLEMENT_PAGE_LIMIT)
+            .await?;
+        let full = page.len() == usize::from(ENTITLEMENT_PAGE_LIMIT);
+        let next = page
+            .iter()
+            .filter_map(|entitlement| entitlement.id.parse::<i64>().ok())
+            .max()
+            .filter(|&id| id > after);
+        entitlements.extend(page);
+        match next {
+            Some(id) if full => after = id,
+            // A full page the cursor cannot move past may hide more entitlements
+            _ => break !full,
+        }
+    };
+    process_user_entitlements(state, user_id, entitlements, complete).await
+}
+
+/// Store a user's entitlement list from Discord and reconcile their tier.
+///
+/// Entitlements Discord marks as deleted are removed from storage. So are
+/// those it no longer lists at all, but only when `complete` says every page
+/// of the list was fetched.
+async fn process_user_entitlements(
+    state: &AppState,
+    user_id: i64,
+    entitlements: Vec<DiscordEntitlement>,
+    complete: bool,
+) -> anyhow::Result<SubscriptionTier> {
+    let mut reported = HashSet::new();
+
+    for entitlement in entitlements {
+        let Some(params) = upsert_params(&entitlement, user_id) else {
+            continue;
+        };
+        let ent_id = params.entitlement_id;
+        if entitlement.deleted {
+            state.storage.delete_entitlement(ent_id).await?;
+            continue;
+        }
+        reported.insert(ent_id);
+
+        // Store entitlement
+        if let Err(e) = state.storage.upsert_entitlement(params).await {
+            tracing::warn!("Failed to upsert entitlement {}: {}", ent_id, e);
+        }
+    }
+
+    // Discord stops listing refunded entitlements instead of marking them deleted
+    if complete {
+        for stored in state.storage.get_user_entitlements(user_id).await? {
+            if !reported.contains(&stored.entitlement_id) {
+                tracing::info!(
+                    "Removing entitlement {} of user {} no longer reported by Discord",
+                    stored.entitlement_id,
+                    user_id
+                );
+                state
+                    .storage
+                    .delete_entitlement(stored.entitlement_id)
+                    .await?;
+            }
+        }
+    } else {
+        tracing::warn!(
+            "Entitlement list of user {} may be incomplete, keeping unlisted entitlements",
+            user_id
+        );
+    }
+
+    match reconcile_subscription(state, user_id).await? {
+        Some(decision) => Ok(decision.tier),
+        None => {
+            let entitlements = state.storage.get_user_entitlements(user_id).await?;
+            let premium = state
+                .config
+                .discord
+                .premium_sku_id
+                .and_then(|sku| premium_grant(&entitlements, sku));
+            Ok(if premium.is_some() {
+                SubscriptionTier::Premium
+            } else {
+                SubscriptionTier::Free
+            })
+        }
+    }
+}
+
+/// Store or delete a single entitlement Discord reported, e.g. in a webhook
+/// event, and reconcile its user's subscription.
+///
+/// Entitlements without a user, and entitlements of users catacombs has not
+/// stored yet, are skipped; those users are synced when they log in. Returns
+/// the reconciliation decision, or `None` if nothing was reconciled.
+///
+/// # Errors
+///    - Returns an error if a storage read or write fails.
+pub async fn apply_entitlement(
+    state: &AppState,
+    entitlement: &DiscordEntitlement,
+) -> anyhow::Result<Option<SubscriptionDecision>> {
+    match store_entitlement(state, entitlement).await? {
+        Some(user_id) => reconcile_subscription(state, user_id).await,
+        None => Ok(None),
+    }
+}
+
+/// Store or delete a single entitlement without reconciling, returning the
+/// ID of its user, or `None` if it was skipped as in `apply_entitlement`.
+pub(crate) async fn store_entitlement(
+    state: &AppState,
+    entitlement: &DiscordEntitlement,
+) -> anyhow::Result<Option<i64>> {
+    let Some(user_id) = entitlement.user_id.as_deref() else {
+        tracing::debug!("Skipping entitlement {} without a user", entitlement.id);
+        return Ok(None);
+    };
+    let Ok(user_id) = user_id.parse::<i64>() else {
+        tracing::warn!("Failed to parse entitlement.user_id '{}'", user_id);
+        return Ok(None);
+    };
+    let Some(params) = upsert_params(entitlement, user_id) else {
+        return Ok(None);
+    };
+    if state
+        .storage
+        .get_user(user_id, &state.config.security.encryption_key)
+        .await?
+        .is_none()
+    {
+        tracing::debug!(
+            "Skipping entitlement {} of unknown user {}",
+            params.entitlement_id,
+            user_id
+        );
+        return Ok(None);
+    }
+
+    if entitlement.deleted {
+        state
+            .storage
+            .delete_entitlement(params.entitlement_id)
+            .await?;
+    } else {
+        state.storage.upsert_entitlement(params).await?;
+    }
+    Ok(Some(user_id))
+}
+
+/// Storage parameters for a Discord entitlement, or `None` if its IDs do not parse.
+fn upsert_params(
+    entitlement: &DiscordEntitlement,
+    user_id: i64,
+) -> Option<EntitlementUpsertParams> {
+    let parse = |field: &str, value: &str| match value.parse::<i64>() {
+        Ok(id) => Some(id),
+        Err(e) => {
+            tracing::warn!("Failed to parse entitlement.{} '{}': {}", field, value, e);
+            None
+        }
+    };
+
+    Some(EntitlementUpsertParams {
+        entitlement_id: parse("id", &entitlement.id)?,
+        user_id,
+        sku_id: parse("sku_id", &entitlement.sku_id)?,
+        entitlement_type: entitlement.entitlement_type,
+        is_test: false,
+        consumed: entitlement.consumed,
+        starts_at: entitlement.starts_at,
+        ends_at: entitlement.ends_at,
+    })
+}
+
+/// Re-derive a user's subscription from their stored entitlements.
+///
+/// Grants or downgrades Discord subscriptions to match the entitlements and
+/// records the decision unless nothing changed. Keeping a `Manual` or
+/// `External` grant is recorded once, until the grant itself changes.
+/// Returns `None` without doing anything if no premium SKU is configured or
+/// the user is not stored.
+///
+/// # Errors
+///    - Returns an error if a storage read or write fails.
+pub async fn reconcile_subscription(
+    state: &AppState,
+    user_id: i64,
+) -> anyhow::Result<Option<SubscriptionDecision>> {
+    let Some(premium_sku_id) = state.config.discord.premium_sku_id else {
+        return Ok(None);
+    };
+    let Some(user) = state
+        .storage
+        .get_user(user_id, &state.config.security.encryption_key)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let entitlements = state.storage.get_user_entitlements(user_id).await?;
+    let mut decision = decide_subscription(&user, &entitlements, premium_sku_id);
+
+    // A grant kept over a lapsed Discord subscription is recorded once, not on every sync
+    if decision.action == SubscriptionAction::Preserved {
+        let previous = state.storage.get_subscription_decisions(user_id, 1).await?;
+        if previous.first().is_some_and(|previous| {
+            previous.action == SubscriptionAction::Preserved
+                && previous.tier == decision.tier
+                && previous.source == decision.source
+                && previous.expires_at == decision.expires_at
+        }) {
+            decision.action = SubscriptionAction::Unchanged;
+        }
+    }
+
+    match decision.action {
+        SubscriptionAction::Granted | SubscriptionAction::Downgraded => {
+            state
+                .storage
+                .update_subscription(
+                    user_id,
+                    decision.tier,
+                    SubscriptionSource::Discord,
+                    decision.expires_at,
+                )
+                .await?;
+        }
+        SubscriptionAction::Preserved | SubscriptionAction::Unchanged => {}
+    }
+
+    if decision.action == SubscriptionAction::Unchanged {
+        tracing::debug!(
+            "Subscription of user {} unchanged: {}",
+            user_id,
+            decision.reason
+        );
+    } else {
+        state
+            .storage
+            .record_subscription_decision(&decision)
+            .await?;
+        tracing::info!(
+            "Subscription of user {} {:?}: {:?} -> {:?} (expires: {:?}): {}",
+            user_id,
+            decision.action,
+            decision.previous_tier,
+            decision.tier,
+            decision.expires_at,
+            decision.reason
+        );
+    }
+
+    Ok(Some(decision))
+}
+
+/// An active entitlement to the premium SKU.
+struct PremiumGrant {
+    entitlement_id: i64,
+    /// When the entitlement ends, None if it does not.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The active premium entitlement that lasts longest, if any.
+fn premium_grant(entitlements: &[Entitlement], premium_sku_id: i64) -> Option<PremiumGrant> {
+    entitlements
+        .iter()
+        .filter(|entitlement| entitlement.sku_id == premium_sku_id && entitlement.is_active())
+        .max_by_key(|entitlement| (entitlement.ends_at.is_none(), entitlement.ends_at))
+        .map(|entitlement| PremiumGrant {
+            entitlement_id: entitlement.entitlement_id,
+            expires_at: entitlement.ends_at,
+        })
+}
+
+/// Decide what a user's entitlements mean for their subscription.
+fn decide_subscription(
+    user: &User,
+    entitlements: &[Entitlement],
+    premium_sku_id: i64,
+) -> SubscriptionDecision {
+    let grant = premium_grant(entitlements, premium_sku_id);
+    let mut decision = SubscriptionDecision {
+        user_id: user.user_id,
+        action: SubscriptionAction::Unchanged,
+        previous_tier: user.subscription_tier,
+        previous_source: user.subscription_source,
+        tier: user.subscription_tier,
+        source: user.subscription_source,
+        expires_at: user.subscription_expires_at,
+        reason: String::new(),
+        decided_at: Utc::now(),
+    };
+    let granted_elsewhere = matches!(
+        user.subscription_source,
+        Some(SubscriptionSource::Manual | SubscriptionSource::External)
+    );
+
+    match grant {
+        // An active manual or external grant takes precedence over Discord
+        Some(_) if granted_elsewhere && user.is_premium() => {
+            decision.reason = "premium entitlement is active, but so is a non-Discord grant".into();
+        }
+        Some(grant) => {
+            decision.reason = format!(
+                "entitlement {} for premium SKU {} is active",
+                grant.entitlement_id, premium_sku_id
+            );
+            let unchanged = user.subscription_tier == SubscriptionTier::Premium
+                && user.subscription_source == Some(SubscriptionSource::Discord)
+                && user.subscription_expires_at == grant.expires_at;
+            if !unchanged {
+                decision.action = SubscriptionAction::Granted;
+                decision.tier = SubscriptionTier::Premium;
+                decision.source = Some(SubscriptionSource::Discord);
+                decision.expires_at = grant.expires_at;
+            }
+        }
+        None if user.subscription_tier == SubscriptionTier::Free => {
+            decision.reason = "no active premium entitlement".into();
+        }
+        None if granted_elsewhere => {
+            decision.reason = format!(
+                "no active premium entitlement; kept {:?} grant",
+                user.subscription_source
+                    .unwrap_or(SubscriptionSource::Manual)
+            );
+            // Only worth recording when a Discord subscription lapsed under it
+            if entitlements.iter().any(|e| e.sku_id == premium_sku_id) {
+                decision.action = SubscriptionAction::Preserved;
+            }
+        }
+        None => {
+            decision.action = SubscriptionAction::Downgraded;
+            decision.reason = "no active premium entitlement remains".into();
+            decision.tier = SubscriptionTier::Free;
+            decision.source = Some(SubscriptionSource::Discord);
+            decision.expires_at = None;
+        }
+    }
+
+    decision
+}
+
+#[cfg(all(test, feature = "memory-storage"))]
+mod tests {
+    use super::*;
+    use crate::{
+        discord::DiscordClient,
+        storage::MemoryStorage,
+        testing::{mock_entitlement, MockDiscord, MockEndpoint, MockResponse},
+    };
+
+    #[tokio::test]
+    async fn test_sync_user_entitlements_grants_premium() {
+        let discord = MockDiscord::start().await.unwrap();
+        discord.respond(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(1, 42, 777)]),
+        );
+        let state = discord.state_with_user(42).await;
+
+        let tier = sync_user_entitlements(&state, 42).await.unwrap();
+        assert_eq!(tier, SubscriptionTier::Premium);
+        let user = state.storage.get_user(42, "").await.unwrap().unwrap();
+        assert!(user.is_premium());
+
+        let requests = discord.requests_to(MockEndpoint::Entitlements);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].uri,
+            "/applications/mock_client_id/entitlements?user_id=42&after=0&limit=100&exclude_ended=false"
+        );
+        assert_eq!(
+            requests[0].authorization.as_deref(),
+            Some("Bot mock_bot_token")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_user_entitlements_pages_before_pruning() {
+        let discord = MockDiscord::start().await.unwrap();
+        let first_page = (1..=100).map(|id| mock_entitlement(id, 42, 1)).collect();
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(first_page),
+        );
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(101, 42, 777)]),
+        );
+        let state = discord.state_with_user(42).await;
+
+        // Only listed on the second page, so the first alone must not prune it
+        let tier = sync_user_entitlements(&state, 42).await.unwrap();
+        assert_eq!(tier, SubscriptionTier::Premium);
+        assert_eq!(
+            state.storage.get_user_entitlements(42).await.unwrap().len(),
+            101
+        );
+
+        let requests = discord.requests_to(MockEndpoint::Entitlements);
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].uri.contains("after=100&limit=100"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_user_entitlements_with_injected_api() {
+        let discord = MockDiscord::start().await.unwrap();
+        let mut ended = mock_entitlement(1, 42, 777);
+        ended["ends_at"] = serde_json::json!(Utc::now() - chrono::Duration::days(1));
+        discord.respond(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![ended]),
+        );
+
+        // Only the injected client knows where the fake is
+        let mut config = discord.config();
+        config.discord.premium_sku_id = Some(777);
+        config.discord.api_base_url = "http://127.0.0.1:9".to_string();
+        let client = DiscordClient::new(discord.config().discord, reqwest::Client::new());
+        let state = AppState::new(config, MemoryStorage::new()).with_discord_api(client);
+
+        let tier = sync_user_entitlements(&state, 42).await.unwrap();
+        assert_eq!(tier, SubscriptionTier::Free);
+        let stored = state.storage.get_user_entitlements(42).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(discord.requests_to(MockEndpoint::Entitlements).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_user_entitlements_downgrades_lapsed_subscription() {
+        let discord = MockDiscord::start().await.unwrap();
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(1, 42, 777)]),
+        );
+        let state = discord.state_with_user(42).await;
+        assert_eq!(
+            sync_user_entitlements(&state, 42).await.unwrap(),
+            SubscriptionTier::Premium
+        );
+
+        // Refunded entitlements disappear from the list
+        let tier = sync_user_entitlements(&state, 42).await.unwrap();
+        assert_eq!(tier, SubscriptionTier::Free);
+        let user = state.storage.get_user(42, "").await.unwrap().unwrap();
+        assert!(!user.is_premium());
+        assert_eq!(user.subscription_source, Some(SubscriptionSource::Discord));
+        assert!(state
+            .storage
+            .get_user_entitlements(42)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let decisions = state
+            .storage
+            .get_subscription_decisions(42, 10)
+            .await
+            .unwrap();
+        let actions: Vec<_> = decisions.iter().map(|d| d.action).collect();
+        assert_eq!(
+            actions,
+            vec![SubscriptionAction::Downgraded, SubscriptionAction::Granted]
+        );
+        assert_eq!(decisions[0].previous_tier, SubscriptionTier::Premium);
+    }
+
+    #[tokio::test]
+    async fn test_sync_user_entitlements_removes_deleted_entitlements() {
+        let discord = MockDiscord::start().await.unwrap();
+        let mut deleted = mock_entitlement(1, 42, 777);
+        deleted["deleted"] = serde_json::Value::Bool(true);
+        discord.respond(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![deleted]),
+        );
+        let state = discord.state_with_user(42).await;
+        state
+            .storage
+            .update_subscription(
+                42,
+                SubscriptionTier::Premium,
+                SubscriptionSource::Discord,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let tier = sync_user_entitlements(&state, 42).await.unwrap();
+        assert_eq!(tier, SubscriptionTier::Free);
+        assert!(state
+            .storage
+            .get_user_entitlements(42)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_user_entitlements_preserves_manual_grants() {
+        let discord = MockDiscord::start().await.unwrap();
+        let mut ended = mock_entitlement(1, 42, 777);
+        ended["ends_at"] = serde_json::json!((Utc::now() - chrono::Duration::days(1)).to_rfc3339());
+        discord.respond(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![ended]),
+        );
+        let state = discord.state_with_user(42).await;
+        state
+            .storage
+            .update_subscription(
+                42,
+                SubscriptionTier::Premium,
+                SubscriptionSource::Manual,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let tier = sync_user_entitlements(&state, 42).await.unwrap();
+        assert_eq!(tier, SubscriptionTier::Premium);
+        let user = state.storage.get_user(42, "").await.unwrap().unwrap();
+        assert_eq!(user.subscription_source, Some(SubscriptionSource::Manual));
+
+        let decisions = state
+            .storage
+            .get_subscription_decisions(42, 10)
+            .await
+            .unwrap();
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].action, SubscriptionAction::Preserved);
+
+        // The grant is still left alone, but the same decision is not recorded again
+        assert_eq!(
+            sync_user_entitlements(&state, 42).await.unwrap(),
+            SubscriptionTier::Premium
+        );
+        assert_eq!(
+            state
+                .storage
+                .get_subscription_decisions(42, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // A new grant is recorded when its Discord subscription has lapsed too
+        let expires_at = Utc::now() + chrono::Duration::days(30);
+        state
+            .storage
+            .update_subscription(
+                42,
+                SubscriptionTier::Premium,
+                SubscriptionSource::Manual,
+                Some(expires_at),
+            )
+            .await
+            .unwrap();
+        sync_user_entitlements(&state, 42).await.unwrap();
+        let decisions = state
+            .storage
+            .get_subscription_decisions(42, 10)
+            .await
+            .unwrap();
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[0].action, SubscriptionAction::Preserved);
+    }
+}
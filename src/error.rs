@@ -40,6 +40,12 @@ pub enum Error {
     InvalidRequest(String),
 }
 
+impl From<crate::discord::DiscordError> for Error {
+    fn from(err: crate::discord::DiscordError) -> Self {
+        Self::DiscordApi(err.to_string())
+    }
+}
+
 /// Storage-specific errors.
 #[derive(Debug, thiserror::Error)]
 pub enum StorageError {
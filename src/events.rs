@@ -0,0 +1,170 @@
+//! In-process bus of account events.
+//!
+//! Storage writes made through `AppState::storage` and the auth route
+//! handlers publish [`AccountEvent`]s here. The WebSocket hub and the
+//! `GET /events` Server-Sent Events stream subscribe and forward each event to
+//! the user it concerns. Host applications can publish and subscribe too.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::models::{SubscriptionSource, SubscriptionTier};
+
+/// Events buffered per subscriber before slow subscribers start missing them.
+const EVENT_BUS_CAPACITY: usize = 256;
+
+/// An event about a user's account.
+///
+/// Serialized as JSON with a `type` tag, e.g.
+/// `{"type":"forced_logout","session_id":null}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountEvent {
+    /// The user's subscription tier, source or expiry changed.
+    SubscriptionChanged {
+        tier: SubscriptionTier,
+        source: SubscriptionSource,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    /// The user's Discord profile was refreshed.
+    ProfileUpdated {
+        username: String,
+        global_name: Option<String>,
+        avatar_url: Option<String>,
+    },
+    /// Every JWT issued to the user so far was invalidated; refresh or log in again.
+    TokensRevoked,
+    /// A session was ended server-side; the client should discard its tokens.
+    /// `session_id` is None when every session of the user was ended.
+    ForcedLogout { session_id: Option<Uuid> },
+}
+
+impl AccountEvent {
+    /// The event's `type` tag.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SubscriptionChanged { .. } => "subscription_changed",
+            Self::ProfileUpdated { .. } => "profile_updated",
+            Self::TokensRevoked => "tokens_revoked",
+            Self::ForcedLogout { .. } => "forced_logout",
+        }
+    }
+
+    /// Returns true if this event ends the given session's connections.
+    #[must_use]
+    pub fn ends_session(&self, session_id: Option<Uuid>) -> bool {
+        match self {
+            Self::ForcedLogout { session_id: None } => true,
+            Self::ForcedLogout { session_id: ended } => *ended == session_id,
+            _ => false,
+        }
+    }
+}
+
+/// An account event addressed to one user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserEvent {
+    /// Discord user ID the event concerns.
+    pub user_id: i64,
+    pub event: AccountEvent,
+}
+
+/// Broadcast bus of [`UserEvent`]s; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<UserEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Create a bus with no subscribers.
+    #[must_use]
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish an event about a user.
+    ///
+    /// Returns the number of subscribers that will receive it; publishing with
+    /// no subscribers is not an error.
+    pub fn publish(&self, user_id: i64, event: AccountEvent) -> usize {
+        self.sender
+            .send(UserEvent { user_id, event })
+            .unwrap_or_default()
+    }
+
+    /// Subscribe to every event published from now on.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<UserEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_event_serialization() {
+        let event = AccountEvent::SubscriptionChanged {
+            tier: SubscriptionTier::Premium,
+            source: SubscriptionSource::Discord,
+            expires_at: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"subscription_changed","tier":"premium","source":"discord","expires_at":null}"#
+        );
+
+        let event = AccountEvent::ForcedLogout { session_id: None };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"forced_logout","session_id":null}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&AccountEvent::TokensRevoked).unwrap(),
+            format!(r#"{{"type":"{}"}}"#, AccountEvent::TokensRevoked.name())
+        );
+    }
+
+    #[test]
+    fn test_ends_session() {
+        let session = Some(Uuid::new_v4());
+        let everywhere = AccountEvent::ForcedLogout { session_id: None };
+        assert!(everywhere.ends_session(session));
+        assert!(everywhere.ends_session(None));
+
+        let one = AccountEvent::ForcedLogout {
+            session_id: session,
+        };
+        assert!(one.ends_session(session));
+        assert!(!one.ends_session(Some(Uuid::new_v4())));
+        assert!(!one.ends_session(None));
+        assert!(!AccountEvent::TokensRevoked.ends_session(session));
+    }
+
+    #[tokio::test]
+    async fn test_event_bus_publish_subscribe() {
+        let bus = EventBus::new();
+        assert_eq!(bus.publish(1, AccountEvent::TokensRevoked), 0);
+
+        let mut receiver = bus.subscribe();
+        assert_eq!(bus.clone().publish(7, AccountEvent::TokensRevoked), 1);
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            UserEvent {
+                user_id: 7,
+                event: AccountEvent::TokensRevoked,
+            }
+        );
+    }
+}
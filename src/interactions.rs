@@ -0,0 +1,366 @@
+//! Discord interactions: slash commands, components and modals.
+//!
+//! Discord posts every interaction with the application to its Interactions
+//! Endpoint URL, served by `routes::interactions_router`. Payloads decode into
+//! [`Interaction`]; the invoking Discord user is looked up in storage, and the
+//! interaction is passed to the [`InteractionHandler`] registered for its
+//! command name or component `custom_id` with `AppState::with_interaction_handler`.
+//!
+//! [`InteractionResponse::premium_required`] answers users without premium
+//! with a button to buy `DiscordConfig::premium_sku_id`.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    config::DiscordConfig,
+    discord::{DiscordEntitlement, DiscordUser},
+    models::User,
+    AppState,
+};
+
+/// Interaction type of the PING Discord sends to validate the endpoint.
+pub const INTERACTION_PING: u8 = 1;
+/// Interaction type of a slash, user or message command.
+pub const INTERACTION_APPLICATION_COMMAND: u8 = 2;
+/// Interaction type of a button or select menu.
+pub const INTERACTION_MESSAGE_COMPONENT: u8 = 3;
+/// Interaction type of an autocomplete request for a command option.
+pub const INTERACTION_AUTOCOMPLETE: u8 = 4;
+/// Interaction type of a submitted modal.
+pub const INTERACTION_MODAL_SUBMIT: u8 = 5;
+
+/// Response type answering a PING.
+pub const RESPONSE_PONG: u8 = 1;
+/// Response type replying with a message.
+pub const RESPONSE_CHANNEL_MESSAGE: u8 = 4;
+/// Response type acknowledging now and replying later with a followup.
+pub const RESPONSE_DEFERRED_CHANNEL_MESSAGE: u8 = 5;
+/// Response type showing the application's premium upsell (deprecated by Discord).
+pub const RESPONSE_PREMIUM_REQUIRED: u8 = 10;
+
+/// Message flag making a reply visible only to the invoking user.
+pub const EPHEMERAL_FLAG: u64 = 1 << 6;
+
+/// Button style linking to a SKU's purchase page.
+const BUTTON_STYLE_PREMIUM: u8 = 6;
+
+/// An interaction Discord sent to the Interactions Endpoint URL.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Interaction {
+    /// Snowflake interaction ID.
+    pub id: String,
+    /// Snowflake ID of the application.
+    pub application_id: String,
+    /// One of the `INTERACTION_*` types.
+    #[serde(rename = "type")]
+    pub kind: u8,
+    /// Command or component data, absent for PINGs.
+    #[serde(default)]
+    pub data: Option<InteractionData>,
+    /// Guild the interaction was sent from, if any.
+    #[serde(default)]
+    pub guild_id: Option<String>,
+    /// Channel the interaction was sent from, if any.
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    /// The invoking guild member, when invoked in a guild.
+    #[serde(default)]
+    pub member: Option<InteractionMember>,
+    /// The invoking user, when invoked in a DM.
+    #[serde(default)]
+    pub user: Option<DiscordUser>,
+    /// Token for followup messages, valid for 15 minutes.
+    pub token: String,
+    /// The invoking user's locale.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// The invoking user's active entitlements to the application.
+    #[serde(default)]
+    pub entitlements: Vec<DiscordEntitlement>,
+}
+
+impl Interaction {
+    /// The invoking Discord user: `member.user` in guilds, `user` in DMs.
+    #[must_use]
+    pub fn invoker(&self) -> Option<&DiscordUser> {
+        self.member
+            .as_ref()
+            .map(|member| &member.user)
+            .or(self.user.as_ref())
+    }
+
+    /// Snowflake ID of the invoking user, if it parses.
+    #[must_use]
+    pub fn user_id(&self) -> Option<i64> {
+        self.invoker().and_then(|user| user.id.parse().ok())
+    }
+
+    /// Command name, or component and modal `custom_id`, that handlers are registered under.
+    #[must_use]
+    pub fn handler_name(&self) -> Option<&str> {
+        let data = self.data.as_ref()?;
+        match self.kind {
+            INTERACTION_APPLICATION_COMMAND | INTERACTION_AUTOCOMPLETE => data.name.as_deref(),
+            INTERACTION_MESSAGE_COMPONENT | INTERACTION_MODAL_SUBMIT => data.custom_id.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// The guild member who invoked an interaction.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InteractionMember {
+    pub user: DiscordUser,
+    /// Guild nickname.
+    #[serde(default)]
+    pub nick: Option<String>,
+    /// Snowflake IDs of the member's roles.
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Command, component or modal data of an interaction.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InteractionData {
+    /// Snowflake command ID.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Command name.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Options the user filled in, for commands.
+    #[serde(default)]
+    pub options: Vec<CommandOption>,
+    /// Developer-defined ID of the component or modal.
+    #[serde(default)]
+    pub custom_id: Option<String>,
+    /// Selected values, for select menus.
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+impl InteractionData {
+    /// The value of a top-level command option.
+    #[must_use]
+    pub fn option(&self, name: &str) -> Option<&Value> {
+        self.options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_ref())
+    }
+}
+
+/// A command option filled in by the user.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandOption {
+    pub name: String,
+    /// Application command option type.
+    #[serde(rename = "type")]
+    pub kind: u8,
+    /// The value, absent for subcommands.
+    #[serde(default)]
+    pub value: Option<Value>,
+    /// Options of a subcommand or subcommand group.
+    #[serde(default)]
+    pub options: Vec<CommandOption>,
+    /// Whether this option is the one being autocompleted.
+    #[serde(default)]
+    pub focused: bool,
+}
+
+/// The reply to an interaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InteractionResponse {
+    /// One of the `RESPONSE_*` types.
+    #[serde(rename = "type")]
+    pub kind: u8,
+    /// Message, autocomplete or modal data, depending on `kind`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl InteractionResponse {
+    /// Answer a PING.
+    #[must_use]
+    pub fn pong() -> Self {
+        Self {
+            kind: RESPONSE_PONG,
+            data: None,
+        }
+    }
+
+    /// Reply with a message.
+    #[must_use]
+    pub fn message(content: impl Into<String>) -> Self {
+        Self {
+            kind: RESPONSE_CHANNEL_MESSAGE,
+            data: Some(json!({ "content": content.into() })),
+        }
+    }
+
+    /// Reply with a message only the invoking user sees.
+    #[must_use]
+    pub fn ephemeral(content: impl Into<String>) -> Self {
+        Self {
+            kind: RESPONSE_CHANNEL_MESSAGE,
+            data: Some(json!({ "content": content.into(), "flags": EPHEMERAL_FLAG })),
+        }
+    }
+
+    /// Acknowledge now and reply later with a followup message.
+    #[must_use]
+    pub fn deferred() -> Self {
+        Self {
+            kind: RESPONSE_DEFERRED_CHANNEL_MESSAGE,
+            data: None,
+        }
+    }
+
+    /// Tell the user the interaction needs premium.
+    ///
+    /// Replies ephemerally with a button to buy `premium_sku_id`. Without a
+    /// configured SKU, falls back to the `RESPONSE_PREMIUM_REQUIRED` upsell.
+    #[must_use]
+    pub fn premium_required(config: &DiscordConfig) -> Self {
+        let Some(sku_id) = config.premium_sku_id else {
+            return Self {
+                kind: RESPONSE_PREMIUM_REQUIRED,
+                data: None,
+            };
+        };
+        Self {
+            kind: RESPONSE_CHANNEL_MESSAGE,
+            data: Some(json!({
+                "content": "This requires premium.",
+                "flags": EPHEMERAL_FLAG,
+                "components": [{
+                    "type": 1,
+                    "components": [{
+                        "type": 2,
+                        "style": BUTTON_STYLE_PREMIUM,
+                        "sku_id": sku_id.to_string(),
+                    }],
+                }],
+            })),
+        }
+    }
+}
+
+/// An interaction with the catacombs user who invoked it.
+#[derive(Debug, Clone)]
+pub struct InteractionRequest {
+    pub interaction: Interaction,
+    /// The stored user matching the invoker, None if they have never logged in.
+    pub user: Option<User>,
+}
+
+impl InteractionRequest {
+    /// Whether the invoking user has an active premium subscription.
+    #[must_use]
+    pub fn is_premium(&self) -> bool {
+        self.user.as_ref().is_some_and(User::is_premium)
+    }
+}
+
+/// Handles the interactions of one command, component or modal.
+#[async_trait]
+pub trait InteractionHandler: Send + Sync {
+    /// Reply to an interaction.
+    ///
+    /// Parameters:
+    ///     - state: `&AppState` - Application state
+    ///     - request: `InteractionRequest` - The interaction and its invoking user
+    /// Returns:
+    ///     - `anyhow::Result<InteractionResponse>` - The reply sent to Discord
+    /// Errors:
+    ///     - Any error; the interaction fails with 500 and Discord shows it as failed
+    async fn handle(
+        &self,
+        state: &AppState,
+        request: InteractionRequest,
+    ) -> anyhow::Result<InteractionResponse>;
+}
+
+/// Registered interaction handlers, keyed by command name or `custom_id`.
+#[derive(Clone, Default)]
+pub struct InteractionHandlers {
+    handlers: HashMap<String, Arc<dyn InteractionHandler>>,
+}
+
+impl InteractionHandlers {
+    /// Register a handler, replacing any previous one under the same name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        handler: impl InteractionHandler + 'static,
+    ) {
+        self.handlers.insert(name.into(), Arc::new(handler));
+    }
+
+    /// The handler for an interaction, if one is registered.
+    #[must_use]
+    pub fn get(&self, interaction: &Interaction) -> Option<Arc<dyn InteractionHandler>> {
+        interaction
+            .handler_name()
+            .and_then(|name| self.handlers.get(name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interaction_decodes_guild_command() {
+        let interaction: Interaction = serde_json::from_value(json!({
+            "id": "1",
+            "application_id": "2",
+            "type": INTERACTION_APPLICATION_COMMAND,
+            "token": "token",
+            "guild_id": "3",
+            "member": {
+                "user": {"id": "42", "username": "alice"},
+                "roles": ["4"],
+            },
+            "data": {
+                "id": "5",
+                "name": "roll",
+                "type": 1,
+                "options": [{"name": "sides", "type": 4, "value": 20}],
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(interaction.user_id(), Some(42));
+        assert_eq!(interaction.handler_name(), Some("roll"));
+        assert_eq!(interaction.data.unwrap().option("sides"), Some(&json!(20)));
+    }
+
+    #[test]
+    fn test_premium_required_links_the_premium_sku() {
+        let mut config: DiscordConfig = serde_json::from_value(json!({
+            "client_id": "1",
+            "client_secret": "s",
+            "redirect_uri": "r",
+            "bot_token": "b",
+        }))
+        .unwrap();
+        assert_eq!(
+            InteractionResponse::premium_required(&config).kind,
+            RESPONSE_PREMIUM_REQUIRED
+        );
+
+        config.premium_sku_id = Some(777);
+        let response =
+            serde_json::to_value(InteractionResponse::premium_required(&config)).unwrap();
+        assert_eq!(response["type"], RESPONSE_CHANNEL_MESSAGE);
+        assert_eq!(response["data"]["flags"], EPHEMERAL_FLAG);
+        let button = &response["data"]["components"][0]["components"][0];
+        assert_eq!(button["style"], BUTTON_STYLE_PREMIUM);
+        assert_eq!(button["sku_id"], "777");
+    }
+}
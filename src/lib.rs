@@ -7,6 +7,7 @@
 //!
 //! - `sqlx-storage` (default): `PostgreSQL` storage via `SQLx`
 //! - `memory-storage`: In-memory storage for testing
+//! - `test-util`: A local mock of the Discord API (`testing::MockDiscord`)
 //!
 //! # Example
 //!
@@ -36,23 +37,40 @@
 
 pub mod auth;
 pub mod config;
+pub mod cookies;
+pub mod discord;
 pub mod encryption;
+pub mod entitlements;
 pub mod error;
+pub mod events;
+pub mod interactions;
+pub mod jwks;
 pub mod models;
+pub mod oauth;
+pub mod reconciliation;
 pub mod routes;
 pub mod storage;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod ws;
 
 // Re-exports for convenience
 use std::sync::Arc;
 
-pub use config::{Config, ConfigError, DiscordConfig, SecurityConfig, ServerConfig};
+pub use config::{
+    Config, ConfigError, CookieSameSite, DiscordConfig, JwtAlgorithm, JwtVerificationKey,
+    SecurityConfig, ServerConfig,
+};
+pub use discord::{DiscordApi, DiscordClient, DiscordError};
 pub use error::{Error, Result, StorageError};
 pub use models::{SubscriptionSource, SubscriptionTier, User};
 #[cfg(feature = "memory-storage")]
 pub use storage::MemoryStorage;
 #[cfg(feature = "sqlx-storage")]
 pub use storage::SqlxStorage;
-pub use storage::{EntitlementStorage, Storage, UserStorage};
+pub use storage::{
+    EntitlementStorage, PublishingStorage, RoleStorage, SessionStorage, Storage, UserStorage,
+};
 
 /// Application state containing configuration and storage.
 ///
@@ -60,34 +78,76 @@ pub use storage::{EntitlementStorage, Storage, UserStorage};
 pub struct AppState {
     /// Application configuration.
     pub config: Config,
-    /// Storage backend for users and entitlements.
+    /// Storage backend for users and entitlements. Writes publish account
+    /// events on `events`.
     pub storage: Box<dyn Storage>,
     /// HTTP client for Discord API requests.
     pub http_client: reqwest::Client,
+    /// Discord API client, a `DiscordClient` over `http_client` unless replaced
+    /// with `with_discord_api`.
+    pub discord: Box<dyn DiscordApi>,
+    /// Open WebSocket connections, for pushing messages to users.
+    pub ws_hub: ws::WsHub,
+    /// Bus of account events, forwarded to users over WebSocket and SSE.
+    pub events: events::EventBus,
+    /// Handlers of Discord interactions, added with `with_interaction_handler`.
+    pub interactions: interactions::InteractionHandlers,
+    /// JWT signing and verification keys, built once from `config.security`.
+    pub jwt_keys: auth::JwtKeyring,
 }
 
 impl AppState {
     /// Create a new `AppState` with the given configuration and storage.
+    ///
+    /// # Panics
+    ///    Panics if the JWT keys in `config.security` cannot be parsed;
+    ///    `Config::from_env` rejects such configurations up front.
     pub fn new(config: Config, storage: impl Storage + 'static) -> Self {
-        Self {
-            config,
-            storage: Box::new(storage),
-            http_client: reqwest::Client::new(),
-        }
+        Self::with_http_client(config, storage, reqwest::Client::new())
     }
 
     /// Create a new `AppState` with a custom HTTP client.
+    ///
+    /// # Panics
+    ///    Panics if the JWT keys in `config.security` cannot be parsed.
     pub fn with_http_client(
         config: Config,
         storage: impl Storage + 'static,
         http_client: reqwest::Client,
     ) -> Self {
+        let jwt_keys = auth::JwtKeyring::from_config(&config.security)
+            .expect("JWT keys in the security configuration are invalid");
+        let events = events::EventBus::new();
+        let discord = DiscordClient::new(config.discord.clone(), http_client.clone());
         Self {
             config,
-            storage: Box::new(storage),
+            storage: Box::new(PublishingStorage::new(storage, events.clone())),
             http_client,
+            discord: Box::new(discord),
+            ws_hub: ws::WsHub::new(events.clone()),
+            events,
+            interactions: interactions::InteractionHandlers::default(),
+            jwt_keys,
         }
     }
+
+    /// Replace the Discord API client, e.g. with a fake in tests.
+    #[must_use]
+    pub fn with_discord_api(mut self, discord: impl DiscordApi + 'static) -> Self {
+        self.discord = Box::new(discord);
+        self
+    }
+
+    /// Handle the Discord interactions of a command name or component `custom_id`.
+    #[must_use]
+    pub fn with_interaction_handler(
+        mut self,
+        name: impl Into<String>,
+        handler: impl interactions::InteractionHandler + 'static,
+    ) -> Self {
+        self.interactions.register(name, handler);
+        self
+    }
 }
 
 /// Type alias for Arc-wrapped `AppState`, commonly used with Axum.
@@ -1,7 +1,17 @@
 //! Data models for Discord OAuth template.
 
+mod refresh_token;
+mod role;
+mod session;
 mod subscription;
+mod subscription_decision;
 mod user;
+mod ws_ticket;
 
+pub use refresh_token::{RefreshTokenParams, RefreshTokenStatus};
+pub use role::{permits, ADMIN_ROLE, WILDCARD_PERMISSION};
+pub use session::{Session, SessionCreateParams};
 pub use subscription::{SubscriptionSource, SubscriptionTier};
-pub use user::{EntitlementUpsertParams, User, UserUpsertParams};
+pub use subscription_decision::{SubscriptionAction, SubscriptionDecision};
+pub use user::{Entitlement, EntitlementUpsertParams, User, UserListParams, UserUpsertParams};
+pub use ws_ticket::{WsTicket, WsTicketParams};
@@ -0,0 +1,27 @@
+//! First-party refresh token models.
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Parameters for storing a newly issued refresh token.
+#[derive(Debug, Clone)]
+pub struct RefreshTokenParams<'a> {
+    /// SHA-256 hash of the opaque token; the token itself is never stored.
+    pub token_hash: &'a str,
+    pub user_id: i64,
+    /// Token family; every rotation of a login's token shares the same family.
+    pub family_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Result of consuming a refresh token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshTokenStatus {
+    /// The token was valid and has now been marked as used.
+    Valid { user_id: i64, family_id: Uuid },
+    /// The token had already been used, so it has likely been stolen.
+    /// The whole family should be revoked.
+    Reused { user_id: i64, family_id: Uuid },
+    /// The token is unknown, expired or revoked.
+    Invalid,
+}
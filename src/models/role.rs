@@ -0,0 +1,29 @@
+//! Role and permission models.
+
+/// Role created by the migrations, granting every permission.
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Permission that grants every other permission.
+pub const WILDCARD_PERMISSION: &str = "*";
+
+/// Returns true if `granted` includes `permission`, directly or through the wildcard.
+#[must_use]
+pub fn permits(granted: &[String], permission: &str) -> bool {
+    granted
+        .iter()
+        .any(|p| p == permission || p == WILDCARD_PERMISSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permits() {
+        let granted = vec!["users:read".to_string()];
+        assert!(permits(&granted, "users:read"));
+        assert!(!permits(&granted, "users:write"));
+        assert!(!permits(&[], "users:read"));
+        assert!(permits(&[WILDCARD_PERMISSION.to_string()], "users:write"));
+    }
+}
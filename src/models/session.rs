@@ -0,0 +1,85 @@
+//! Login session model.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A login session, created when a user logs in on a device.
+///
+/// The session ID is the `jti` of the first JWT issued for the login. Later
+/// JWTs and refresh tokens for the same login carry it as their `sid` claim
+/// and token family, so revoking the session ends all of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Session ID.
+    pub session_id: Uuid,
+    /// Discord user ID of the session owner.
+    pub user_id: i64,
+    /// `User-Agent` of the client that logged in.
+    pub user_agent: Option<String>,
+    /// IP address of the client that logged in.
+    pub ip_address: Option<String>,
+    /// When the session was created.
+    pub created_at: DateTime<Utc>,
+    /// When the session was last used to authenticate a request.
+    pub last_seen_at: DateTime<Utc>,
+    /// When the session ends unless it is refreshed.
+    pub expires_at: DateTime<Utc>,
+    /// When the session was revoked, if it was.
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// Returns true if the session has not been revoked and has not expired.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
+
+/// Parameters for creating a session.
+#[derive(Debug, Clone)]
+pub struct SessionCreateParams<'a> {
+    pub session_id: Uuid,
+    pub user_id: i64,
+    pub user_agent: Option<&'a str>,
+    pub ip_address: Option<&'a str>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn make_test_session() -> Session {
+        Session {
+            session_id: Uuid::new_v4(),
+            user_id: 123456789,
+            user_agent: Some("Mozilla/5.0".to_string()),
+            ip_address: Some("203.0.113.7".to_string()),
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            expires_at: Utc::now() + Duration::days(30),
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_session_is_active() {
+        let session = make_test_session();
+        assert!(session.is_active());
+    }
+
+    #[test]
+    fn test_session_revoked_or_expired() {
+        let mut revoked = make_test_session();
+        revoked.revoked_at = Some(Utc::now());
+        assert!(!revoked.is_active());
+
+        let mut expired = make_test_session();
+        expired.expires_at = Utc::now() - Duration::seconds(1);
+        assert!(!expired.is_active());
+    }
+}
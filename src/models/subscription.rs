@@ -15,7 +15,7 @@ pub enum SubscriptionTier {
 
 impl SubscriptionTier {
     /// Returns true if this tier grants premium access.
-    #[must_use] 
+    #[must_use]
     pub fn is_premium(&self) -> bool {
         matches!(self, Self::Premium)
     }
@@ -38,6 +38,10 @@ impl sqlx::Type<sqlx::Postgres> for SubscriptionTier {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
         <String as sqlx::Type<sqlx::Postgres>>::type_info()
     }
+
+    fn compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {
+        <String as sqlx::Type<sqlx::Postgres>>::compatible(ty)
+    }
 }
 
 #[cfg(feature = "sqlx-storage")]
@@ -71,6 +75,10 @@ impl sqlx::Type<sqlx::Postgres> for SubscriptionSource {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
         <String as sqlx::Type<sqlx::Postgres>>::type_info()
     }
+
+    fn compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {
+        <String as sqlx::Type<sqlx::Postgres>>::compatible(ty)
+    }
 }
 
 #[cfg(feature = "sqlx-storage")]
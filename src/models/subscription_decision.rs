@@ -0,0 +1,80 @@
+//! Audit records of entitlement reconciliation.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::subscription::{SubscriptionSource, SubscriptionTier};
+
+/// What reconciling a user's entitlements did to their subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionAction {
+    /// An active premium entitlement granted or extended a Discord subscription.
+    Granted,
+    /// No premium entitlement remains, so a Discord subscription was set to free.
+    Downgraded,
+    /// The entitlements disagree with a `Manual` or `External` grant, which was left alone.
+    Preserved,
+    /// The subscription already matched the entitlements. Not recorded.
+    Unchanged,
+}
+
+/// A reconciliation decision for one user, recorded for auditing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionDecision {
+    pub user_id: i64,
+    pub action: SubscriptionAction,
+    /// Tier before reconciliation.
+    pub previous_tier: SubscriptionTier,
+    /// Source before reconciliation.
+    pub previous_source: Option<SubscriptionSource>,
+    /// Tier after reconciliation.
+    pub tier: SubscriptionTier,
+    /// Source after reconciliation.
+    pub source: Option<SubscriptionSource>,
+    /// Expiry after reconciliation (None = lifetime or free).
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Human-readable explanation, e.g. which entitlement granted premium.
+    pub reason: String,
+    pub decided_at: DateTime<Utc>,
+}
+
+#[cfg(feature = "sqlx-storage")]
+impl sqlx::Type<sqlx::Postgres> for SubscriptionAction {
+    fn type_info() -> sqlx::postgres::PgTypeInfo {
+        <String as sqlx::Type<sqlx::Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {
+        <String as sqlx::Type<sqlx::Postgres>>::compatible(ty)
+    }
+}
+
+#[cfg(feature = "sqlx-storage")]
+impl<'r> sqlx::Decode<'r, sqlx::Postgres> for SubscriptionAction {
+    fn decode(value: sqlx::postgres::PgValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        let s = <String as sqlx::Decode<sqlx::Postgres>>::decode(value)?;
+        match s.as_str() {
+            "granted" => Ok(Self::Granted),
+            "downgraded" => Ok(Self::Downgraded),
+            "preserved" => Ok(Self::Preserved),
+            _ => Ok(Self::Unchanged),
+        }
+    }
+}
+
+#[cfg(feature = "sqlx-storage")]
+impl sqlx::Encode<'_, sqlx::Postgres> for SubscriptionAction {
+    fn encode_by_ref(
+        &self,
+        buf: &mut sqlx::postgres::PgArgumentBuffer,
+    ) -> Result<sqlx::encode::IsNull, sqlx::error::BoxDynError> {
+        let s = match self {
+            Self::Granted => "granted",
+            Self::Downgraded => "downgraded",
+            Self::Preserved => "preserved",
+            Self::Unchanged => "unchanged",
+        };
+        <&str as sqlx::Encode<sqlx::Postgres>>::encode(s, buf)
+    }
+}
@@ -21,6 +21,12 @@ pub struct User {
     pub refresh_token: Option<String>,
     /// When the Discord OAuth token expires.
     pub token_expires_at: Option<DateTime<Utc>>,
+    /// `OAuth2` scopes the user granted during their most recent login.
+    #[serde(default)]
+    pub granted_scopes: Vec<String>,
+    /// JWTs carrying an older token version are rejected.
+    #[serde(default)]
+    pub token_version: i32,
     /// User's subscription tier.
     pub subscription_tier: SubscriptionTier,
     /// Source of the user's subscription.
@@ -35,7 +41,7 @@ pub struct User {
 
 impl User {
     /// Returns true if the user has an active premium subscription.
-    #[must_use] 
+    #[must_use]
     pub fn is_premium(&self) -> bool {
         if !self.subscription_tier.is_premium() {
             return false;
@@ -48,8 +54,14 @@ impl User {
         }
     }
 
+    /// Returns true if the user granted the given `OAuth2` scope (e.g. `guilds`, `email`).
+    #[must_use]
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.granted_scopes.iter().any(|s| s == scope)
+    }
+
     /// Returns the display name for the user, preferring `global_name` over username.
-    #[must_use] 
+    #[must_use]
     pub fn display_name(&self) -> &str {
         self.global_name.as_deref().unwrap_or(&self.username)
     }
@@ -64,6 +76,8 @@ pub struct UserUpsertParams<'a> {
     pub avatar_url: Option<&'a str>,
     pub refresh_token: Option<&'a str>,
     pub token_expires_at: Option<DateTime<Utc>>,
+    /// Granted `OAuth2` scopes; `None` keeps the stored scopes unchanged.
+    pub granted_scopes: Option<&'a [String]>,
 }
 
 /// Parameters for upserting an entitlement.
@@ -79,6 +93,76 @@ pub struct EntitlementUpsertParams {
     pub ends_at: Option<DateTime<Utc>>,
 }
 
+/// A Discord entitlement granting a user access to a SKU.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entitlement {
+    /// Discord entitlement ID.
+    pub entitlement_id: i64,
+    /// Discord user ID the entitlement belongs to.
+    pub user_id: i64,
+    /// Discord SKU ID.
+    pub sku_id: i64,
+    /// Discord entitlement type (e.g. 8 for an application subscription).
+    pub entitlement_type: i32,
+    /// Whether this is a test entitlement.
+    pub is_test: bool,
+    /// Whether a one-time purchase was consumed.
+    pub consumed: bool,
+    /// When the entitlement starts (None = already started).
+    pub starts_at: Option<DateTime<Utc>>,
+    /// When the entitlement ends (None = never).
+    pub ends_at: Option<DateTime<Utc>>,
+    /// When the entitlement was first stored.
+    pub created_at: DateTime<Utc>,
+    /// When the entitlement was last updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Entitlement {
+    /// Returns true if the entitlement is unconsumed and within its validity window.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        let now = Utc::now();
+        !self.consumed
+            && self.starts_at.map_or(true, |starts| starts <= now)
+            && self.ends_at.map_or(true, |ends| ends > now)
+    }
+}
+
+/// Parameters for listing users.
+#[derive(Debug, Clone, Default)]
+pub struct UserListParams<'a> {
+    /// Case-insensitive substring of the username or display name, or an exact user ID.
+    pub search: Option<&'a str>,
+    /// Only return users on this tier.
+    pub tier: Option<SubscriptionTier>,
+    /// Maximum number of users to return.
+    pub limit: i64,
+    /// Number of users to skip, for pagination.
+    pub offset: i64,
+}
+
+impl UserListParams<'_> {
+    /// Returns true if the user matches the search and tier filters.
+    #[must_use]
+    pub fn matches(&self, user: &User) -> bool {
+        let search_matches = self.search.map_or(true, |search| {
+            let search = search.to_lowercase();
+            search.parse::<i64>().ok() == Some(user.user_id)
+                || user.username.to_lowercase().contains(&search)
+                || user
+                    .global_name
+                    .as_ref()
+                    .is_some_and(|name| name.to_lowercase().contains(&search))
+        });
+
+        search_matches
+            && self
+                .tier
+                .map_or(true, |tier| user.subscription_tier == tier)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::Duration;
@@ -93,6 +177,8 @@ mod tests {
             avatar_url: Some("https://cdn.discordapp.com/avatars/123/abc.png".to_string()),
             refresh_token: None,
             token_expires_at: None,
+            granted_scopes: vec!["identify".to_string()],
+            token_version: 0,
             subscription_tier: SubscriptionTier::Free,
             subscription_source: None,
             subscription_expires_at: None,
@@ -131,6 +217,16 @@ mod tests {
         assert!(user.is_premium());
     }
 
+    #[test]
+    fn test_user_has_scope() {
+        let mut user = make_test_user();
+        assert!(user.has_scope("identify"));
+        assert!(!user.has_scope("email"));
+
+        user.granted_scopes.push("email".to_string());
+        assert!(user.has_scope("email"));
+    }
+
     #[test]
     fn test_display_name_prefers_global_name() {
         let user = make_test_user();
@@ -153,4 +249,54 @@ mod tests {
         assert!(!json.contains("secret_token"));
         assert!(!json.contains("refresh_token"));
     }
+
+    #[test]
+    fn test_user_list_params_matches() {
+        let user = make_test_user();
+        assert!(UserListParams::default().matches(&user));
+
+        let search = |search| UserListParams {
+            search: Some(search),
+            ..UserListParams::default()
+        };
+        assert!(search("TESTU").matches(&user));
+        assert!(search("test user").matches(&user));
+        assert!(search("123456789").matches(&user));
+        assert!(!search("12345").matches(&user));
+        assert!(!search("someone").matches(&user));
+
+        let premium = UserListParams {
+            tier: Some(SubscriptionTier::Premium),
+            ..UserListParams::default()
+        };
+        assert!(!premium.matches(&user));
+    }
+
+    #[test]
+    fn test_entitlement_is_active() {
+        let mut entitlement = Entitlement {
+            entitlement_id: 1,
+            user_id: 123456789,
+            sku_id: 42,
+            entitlement_type: 8,
+            is_test: false,
+            consumed: false,
+            starts_at: Some(Utc::now() - Duration::days(1)),
+            ends_at: Some(Utc::now() + Duration::days(30)),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        assert!(entitlement.is_active());
+
+        entitlement.ends_at = Some(Utc::now() - Duration::hours(1));
+        assert!(!entitlement.is_active());
+
+        entitlement.ends_at = None;
+        entitlement.starts_at = Some(Utc::now() + Duration::days(1));
+        assert!(!entitlement.is_active());
+
+        entitlement.starts_at = None;
+        entitlement.consumed = true;
+        assert!(!entitlement.is_active());
+    }
 }
@@ -0,0 +1,37 @@
+//! WebSocket ticket models.
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// A single-use ticket that authenticates one WebSocket upgrade.
+///
+/// Tickets are issued by `POST /ws-ticket` to a user who is already
+/// authenticated, so the long-lived JWT never has to appear in a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsTicket {
+    /// Discord user ID the ticket was issued to.
+    pub user_id: i64,
+    /// Username at the time the ticket was issued.
+    pub username: String,
+    /// `OAuth2` scopes carried by the JWT the ticket was issued for.
+    pub scopes: Vec<String>,
+    /// Roles carried by the JWT the ticket was issued for.
+    pub roles: Vec<String>,
+    /// Login session of the JWT the ticket was issued for, if any.
+    pub session_id: Option<Uuid>,
+    /// When the ticket stops being accepted.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Parameters for storing a newly issued WebSocket ticket.
+#[derive(Debug, Clone)]
+pub struct WsTicketParams<'a> {
+    /// SHA-256 hash of the ticket; the ticket itself is never stored.
+    pub ticket_hash: &'a str,
+    pub user_id: i64,
+    pub username: &'a str,
+    pub scopes: &'a [String],
+    pub roles: &'a [String],
+    pub session_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+}
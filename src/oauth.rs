@@ -0,0 +1,291 @@
+//! `OAuth2` authorization helpers.
+//!
+//! This module provides PKCE (RFC 7636) verifier/challenge generation and an
+//! HMAC-signed, expiring `state` value that binds an authorization request to
+//! its PKCE challenge. Both are verified before a code is sent to Discord.
+//!
+//! The signature only proves that this server issued the state for that
+//! challenge. Routes also redeem the state's nonce with
+//! `SessionStorage::consume_oauth_state`, so a state cannot be replayed
+//! within its TTL.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use oauth2::{CsrfToken, PkceCodeChallenge, PkceCodeVerifier};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    config::DiscordConfig,
+    error::{Error, Result},
+};
+
+/// Discord's browser-facing `OAuth2` authorize endpoint.
+pub const DISCORD_AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+
+/// Discord's REST API base URL, used unless `DiscordConfig::api_base_url` overrides it.
+pub const DISCORD_API_BASE_URL: &str = "https://discord.com/api/v10";
+
+/// How long a signed `state` value remains valid, in seconds.
+pub const STATE_TTL_SECONDS: i64 = 600;
+
+/// Domain separator so state signatures can never collide with other uses of the secret.
+const STATE_SIGNING_CONTEXT: &[u8] = b"catacombs-oauth-state:";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A freshly generated authorization request.
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    /// Signed `state` value to pass to Discord's authorize endpoint.
+    pub state: String,
+    /// PKCE code challenge (S256) to pass to Discord's authorize endpoint.
+    pub code_challenge: String,
+    /// PKCE code verifier; must be kept by the client and sent back on exchange.
+    pub code_verifier: String,
+}
+
+/// A `state` value that passed verification.
+#[derive(Debug, Clone)]
+pub struct VerifiedState {
+    /// Nonce to redeem with `SessionStorage::consume_oauth_state`.
+    pub nonce: String,
+    /// When the state expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Payload carried inside a signed `state` value.
+#[derive(Debug, Serialize, Deserialize)]
+struct StatePayload {
+    /// Random nonce so every state is unique.
+    nonce: String,
+    /// PKCE code challenge this state was issued for.
+    challenge: String,
+    /// Expiration timestamp (Unix epoch seconds).
+    exp: i64,
+}
+
+/// Generate a PKCE verifier/challenge pair and a signed `state` bound to it.
+///
+/// # Errors
+///    - Returns `Error::InvalidRequest` if the state payload cannot be encoded.
+pub fn begin_authorization(secret: &str) -> Result<AuthorizationRequest> {
+    let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+    let state = sign_state(challenge.as_str(), secret)?;
+
+    Ok(AuthorizationRequest {
+        state,
+        code_challenge: challenge.as_str().to_string(),
+        code_verifier: verifier.secret().clone(),
+    })
+}
+
+/// Create a signed, expiring `state` value for the given PKCE code challenge.
+///
+/// The format is `base64url(payload).base64url(hmac_sha256(payload))`.
+///
+/// # Errors
+///    - Returns `Error::InvalidRequest` if the state payload cannot be encoded.
+pub fn sign_state(code_challenge: &str, secret: &str) -> Result<String> {
+    let payload = StatePayload {
+        nonce: CsrfToken::new_random().secret().clone(),
+        challenge: code_challenge.to_string(),
+        exp: Utc::now().timestamp() + STATE_TTL_SECONDS,
+    };
+
+    let json = serde_json::to_vec(&payload)
+        .map_err(|e| Error::InvalidRequest(format!("failed to encode state: {e}")))?;
+    let encoded = BASE64_URL.encode(json);
+    let signature = BASE64_URL.encode(state_mac(&encoded, secret).finalize().into_bytes());
+
+    Ok(format!("{encoded}.{signature}"))
+}
+
+/// Verify a signed `state` value and the PKCE code verifier it was issued for.
+///
+/// Verification is stateless; callers must still redeem the returned nonce so
+/// the state is single-use.
+///
+/// # Errors
+///    - Returns `Error::AuthFailed` if the signature is invalid, the state has
+///      expired, or the verifier does not match the challenge in the state.
+pub fn verify_state(state: &str, code_verifier: &str, secret: &str) -> Result<VerifiedState> {
+    let (encoded, signature) = state
+        .split_once('.')
+        .ok_or_else(|| Error::AuthFailed("malformed state".to_string()))?;
+
+    let signature = BASE64_URL
+        .decode(signature)
+        .map_err(|_| Error::AuthFailed("malformed state signature".to_string()))?;
+    state_mac(encoded, secret)
+        .verify_slice(&signature)
+        .map_err(|_| Error::AuthFailed("invalid state signature".to_string()))?;
+
+    let payload: StatePayload = BASE64_URL
+        .decode(encoded)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| Error::AuthFailed("malformed state payload".to_string()))?;
+
+    if payload.exp < Utc::now().timestamp() {
+        return Err(Error::AuthFailed("state has expired".to_string()));
+    }
+
+    let verifier = PkceCodeVerifier::new(code_verifier.to_string());
+    let challenge = PkceCodeChallenge::from_code_verifier_sha256(&verifier);
+    if challenge.as_str() != payload.challenge {
+        return Err(Error::AuthFailed(
+            "code verifier does not match state".to_string(),
+        ));
+    }
+
+    let expires_at = DateTime::from_timestamp(payload.exp, 0)
+        .ok_or_else(|| Error::AuthFailed("malformed state payload".to_string()))?;
+    Ok(VerifiedState {
+        nonce: payload.nonce,
+        expires_at,
+    })
+}
+
+/// Build the Discord authorize URL for an authorization request.
+///
+/// Uses `client_id`, `redirect_uri` and `scopes` from the Discord configuration.
+///
+/// # Errors
+///    - Returns `Error::InvalidRequest` if the URL cannot be built.
+pub fn authorize_url(
+    discord: &DiscordConfig,
+    request: &AuthorizationRequest,
+) -> Result<reqwest::Url> {
+    let scope = discord.scopes.join(" ");
+    reqwest::Url::parse_with_params(
+        DISCORD_AUTHORIZE_URL,
+        &[
+            ("response_type", "code"),
+            ("client_id", discord.client_id.as_str()),
+            ("redirect_uri", discord.redirect_uri.as_str()),
+            ("scope", scope.as_str()),
+            ("state", request.state.as_str()),
+            ("code_challenge", request.code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| Error::InvalidRequest(format!("failed to build authorize URL: {e}")))
+}
+
+fn state_mac(encoded_payload: &str, secret: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(STATE_SIGNING_CONTEXT);
+    mac.update(encoded_payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SECRET: &str = "test-state-secret-for-unit-tests-only";
+
+    #[test]
+    fn test_begin_authorization_round_trip() {
+        let request = begin_authorization(TEST_SECRET).unwrap();
+        assert!(!request.code_challenge.is_empty());
+        assert!(verify_state(&request.state, &request.code_verifier, TEST_SECRET).is_ok());
+    }
+
+    #[test]
+    fn test_verify_state_wrong_secret() {
+        let request = begin_authorization(TEST_SECRET).unwrap();
+        let result = verify_state(&request.state, &request.code_verifier, "wrong-secret");
+        assert!(
+            result.is_err(),
+            "State signed with another secret should fail"
+        );
+    }
+
+    #[test]
+    fn test_verify_state_wrong_verifier() {
+        let request = begin_authorization(TEST_SECRET).unwrap();
+        let other = begin_authorization(TEST_SECRET).unwrap();
+        let result = verify_state(&request.state, &other.code_verifier, TEST_SECRET);
+        assert!(result.is_err(), "Verifier from another request should fail");
+    }
+
+    #[test]
+    fn test_verify_state_tampered_payload() {
+        let request = begin_authorization(TEST_SECRET).unwrap();
+        let (_, signature) = request.state.split_once('.').unwrap();
+        let forged = StatePayload {
+            nonce: "forged".to_string(),
+            challenge: request.code_challenge.clone(),
+            exp: Utc::now().timestamp() + STATE_TTL_SECONDS,
+        };
+        let forged_state = format!(
+            "{}.{}",
+            BASE64_URL.encode(serde_json::to_vec(&forged).unwrap()),
+            signature
+        );
+
+        let result = verify_state(&forged_state, &request.code_verifier, TEST_SECRET);
+        assert!(result.is_err(), "Tampered state should fail");
+    }
+
+    #[test]
+    fn test_verify_state_expired() {
+        let request = begin_authorization(TEST_SECRET).unwrap();
+        let expired = StatePayload {
+            nonce: "nonce".to_string(),
+            challenge: request.code_challenge.clone(),
+            exp: Utc::now().timestamp() - 1,
+        };
+        let encoded = BASE64_URL.encode(serde_json::to_vec(&expired).unwrap());
+        let signature = BASE64_URL.encode(state_mac(&encoded, TEST_SECRET).finalize().into_bytes());
+
+        let result = verify_state(
+            &format!("{encoded}.{signature}"),
+            &request.code_verifier,
+            TEST_SECRET,
+        );
+        assert!(result.is_err(), "Expired state should fail");
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let discord = DiscordConfig {
+            client_id: "1234".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: "http://localhost:3000/auth/callback".to_string(),
+            bot_token: "bot".to_string(),
+            premium_sku_id: None,
+            scopes: vec!["identify".to_string(), "email".to_string()],
+            post_login_redirect_uri: "/".to_string(),
+            api_base_url: DISCORD_API_BASE_URL.to_string(),
+            rate_limit_max_retries: 3,
+            rate_limit_max_wait_seconds: 10,
+            public_key: None,
+            entitlement_reconcile_interval_seconds: 3600,
+        };
+        let request = begin_authorization(TEST_SECRET).unwrap();
+        let url = authorize_url(&discord, &request).unwrap();
+        let params: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+        assert!(url.as_str().starts_with(DISCORD_AUTHORIZE_URL));
+        assert_eq!(params["client_id"], "1234");
+        assert_eq!(
+            params["redirect_uri"],
+            "http://localhost:3000/auth/callback"
+        );
+        assert_eq!(params["scope"], "identify email");
+        assert_eq!(params["state"], request.state);
+        assert_eq!(params["code_challenge"], request.code_challenge);
+        assert_eq!(params["code_challenge_method"], "S256");
+    }
+
+    #[test]
+    fn test_verify_state_malformed() {
+        assert!(verify_state("not-a-state", "verifier", TEST_SECRET).is_err());
+        assert!(verify_state("a.b", "verifier", TEST_SECRET).is_err());
+    }
+}
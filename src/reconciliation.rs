@@ -0,0 +1,415 @@
+//! Periodic reconciliation of every entitlement with Discord.
+//!
+//! Webhook events and logins keep most subscriptions current, but a missed
+//! event or an entitlement that simply ran out leaves a user on the wrong
+//! tier until they log in again. [`reconcile_all_entitlements`] pages through
+//! every entitlement of the application, stores them and re-derives the tier
+//! of each affected user with `entitlements::reconcile_subscription`.
+//! [`spawn_reconciliation_job`] runs it on an interval; `catacombs-admin
+//! reconcile-entitlements` runs it once.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    discord::ENTITLEMENT_PAGE_LIMIT,
+    entitlements,
+    models::{
+        SubscriptionAction, SubscriptionDecision, SubscriptionSource, SubscriptionTier,
+        UserListParams,
+    },
+    AppState, SharedState,
+};
+
+/// Users fetched per page when looking for premium Discord subscribers.
+const USER_PAGE_SIZE: i64 = 200;
+
+/// What one reconciliation run did.
+#[derive(Debug, Clone, Default)]
+pub struct ReconciliationReport {
+    /// Pages of entitlements fetched from Discord.
+    pub pages: usize,
+    /// Entitlements Discord listed, including ended and deleted ones.
+    pub entitlements_seen: usize,
+    /// Entitlements stored or updated.
+    pub entitlements_stored: usize,
+    /// Entitlements removed, because Discord deleted them or no longer lists them.
+    pub entitlements_deleted: usize,
+    /// Entitlements skipped, e.g. of users who have never logged in.
+    pub entitlements_skipped: usize,
+    /// Users whose subscription was reconciled.
+    pub users_reconciled: usize,
+    /// Decisions that changed or preserved a subscription; unchanged ones are left out.
+    pub decisions: Vec<SubscriptionDecision>,
+    /// Storage failures, each logged and skipped.
+    pub errors: usize,
+}
+
+impl ReconciliationReport {
+    /// Number of decisions with the given action.
+    #[must_use]
+    pub fn count(&self, action: SubscriptionAction) -> usize {
+        self.decisions
+            .iter()
+            .filter(|decision| decision.action == action)
+            .count()
+    }
+}
+
+impl fmt::Display for ReconciliationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entitlements in {} pages ({} stored, {} deleted, {} skipped); \
+             {} users reconciled ({} granted, {} downgraded, {} preserved); {} errors",
+            self.entitlements_seen,
+            self.pages,
+            self.entitlements_stored,
+            self.entitlements_deleted,
+            self.entitlements_skipped,
+            self.users_reconciled,
+            self.count(SubscriptionAction::Granted),
+            self.count(SubscriptionAction::Downgraded),
+            self.count(SubscriptionAction::Preserved),
+            self.errors
+        )
+    }
+}
+
+/// Reconcile every entitlement of the application with storage.
+///
+/// Stores each entitlement Discord lists, deletes the ones it marks deleted,
+/// and reconciles the subscription of every user they belong to. Premium
+/// Discord subscribers are reconciled too, after removing stored entitlements
+/// Discord no longer lists, so refunds and lapsed subscriptions are caught.
+/// Entitlements stored after the run started, e.g. by a webhook event or a
+/// login while Discord was being paged, are never removed.
+///
+/// # Errors
+///    - Returns an error if a Discord request fails, before anything is
+///      deleted. Storage failures are counted in the report instead.
+pub async fn reconcile_all_entitlements(state: &AppState) -> anyhow::Result<ReconciliationReport> {
+    let started = Utc::now();
+    let mut report = ReconciliationReport::default();
+    let mut seen = HashSet::new();
+    let mut affected = BTreeSet::new();
+
+    let mut after = 0;
+    loop {
+        let page = state
+            .discord
+            .list_entitlements(after, ENTITLEMENT_PAGE_LIMIT)
+            .await?;
+        if page.is_empty() {
+            break;
+        }
+        report.pages += 1;
+        report.entitlements_seen += page.len();
+
+        let next = page
+            .iter()
+            .filter_map(|entitlement| entitlement.id.parse::<i64>().ok())
+            .max()
+            .filter(|&id| id > after);
+        for entitlement in &page {
+            if let Ok(id) = entitlement.id.parse::<i64>() {
+                seen.insert(id);
+            }
+            match entitlements::store_entitlement(state, entitlement).await {
+                Ok(Some(user_id)) => {
+                    affected.insert(user_id);
+                    if entitlement.deleted {
+                        report.entitlements_deleted += 1;
+                    } else {
+                        report.entitlements_stored += 1;
+                    }
+                }
+                Ok(None) => report.entitlements_skipped += 1,
+                Err(e) => {
+                    tracing::error!("Failed to store entitlement {}: {:#}", entitlement.id, e);
+                    report.errors += 1;
+                }
+            }
+        }
+
+        match next {
+            Some(id) if page.len() == usize::from(ENTITLEMENT_PAGE_LIMIT) => after = id,
+            _ => break,
+        }
+    }
+
+    // Discord stops listing refunded entitlements, and ended subscriptions
+    // produce no event at all
+    match premium_discord_users(state).await {
+        Ok(user_ids) => {
+            for user_id in user_ids {
+                match remove_unlisted(state, user_id, &seen, started).await {
+                    Ok(removed) => report.entitlements_deleted += removed,
+                    Err(e) => {
+                        tracing::error!("Failed to prune entitlements of {}: {:#}", user_id, e);
+                        report.errors += 1;
+                    }
+                }
+                affected.insert(user_id);
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to list premium users: {:#}", e);
+            report.errors += 1;
+        }
+    }
+
+    for user_id in affected {
+        match entitlements::reconcile_subscription(state, user_id).await {
+            Ok(Some(decision)) => {
+                report.users_reconciled += 1;
+                if decision.action != SubscriptionAction::Unchanged {
+                    report.decisions.push(decision);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to reconcile user {}: {:#}", user_id, e);
+                report.errors += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// IDs of premium users whose subscription comes from Discord.
+async fn premium_discord_users(state: &AppState) -> anyhow::Result<Vec<i64>> {
+    let mut user_ids = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page = state
+            .storage
+            .list_users(UserListParams {
+                tier: Some(SubscriptionTier::Premium),
+                limit: USER_PAGE_SIZE,
+                offset,
+                ..UserListParams::default()
+            })
+            .await?;
+        offset += USER_PAGE_SIZE;
+        user_ids.extend(
+            page.iter()
+                .filter(|user| user.subscription_source == Some(SubscriptionSource::Discord))
+                .map(|user| user.user_id),
+        );
+        if (page.len() as i64) < USER_PAGE_SIZE {
+            return Ok(user_ids);
+        }
+    }
+}
+
+/// Delete a user's stored entitlements that Discord did not list.
+///
+/// Only entitlements last stored before `started` are considered; newer ones
+/// may have been created after Discord's listing was fetched.
+async fn remove_unlisted(
+    state: &AppState,
+    user_id: i64,
+    seen: &HashSet<i64>,
+    started: DateTime<Utc>,
+) -> anyhow::Result<usize> {
+    let mut removed = 0;
+    for stored in state.storage.get_user_entitlements(user_id).await? {
+        if stored.updated_at < started && !seen.contains(&stored.entitlement_id) {
+            tracing::info!(
+                "Removing entitlement {} of user {} no longer listed by Discord",
+                stored.entitlement_id,
+                user_id
+            );
+            state
+                .storage
+                .delete_entitlement(stored.entitlement_id)
+                .await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Run `reconcile_all_entitlements` every `interval`, starting immediately.
+///
+/// Each run's report is logged; a failed run is logged and retried at the
+/// next tick. Abort the returned handle to stop the job.
+#[must_use]
+pub fn spawn_reconciliation_job(state: SharedState, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match reconcile_all_entitlements(&state).await {
+                Ok(report) => tracing::info!("Entitlement reconciliation: {}", report),
+                Err(e) => tracing::error!("Entitlement reconciliation failed: {:#}", e),
+            }
+        }
+    })
+}
+
+#[cfg(all(test, feature = "memory-storage"))]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{
+        mock_entitlement, store_mock_user, MockDiscord, MockEndpoint, MockResponse,
+    };
+
+    async fn tier(state: &AppState, user_id: i64) -> SubscriptionTier {
+        let key = &state.config.security.encryption_key;
+        let user = state.storage.get_user(user_id, key).await.unwrap().unwrap();
+        user.subscription_tier
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_all_entitlements_pages_and_fixes_tiers() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = discord.state_with_user(1).await;
+        for user_id in [2, 3] {
+            store_mock_user(&state, user_id).await;
+        }
+
+        // User 3 was granted premium by an entitlement that has since been refunded
+        state
+            .storage
+            .update_subscription(
+                3,
+                SubscriptionTier::Premium,
+                SubscriptionSource::Discord,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let full_page: Vec<_> = (1..=100)
+            .map(|id| mock_entitlement(id, 1, if id == 100 { 777 } else { 555 }))
+            .collect();
+        let mut expired = mock_entitlement(101, 2, 777);
+        expired["ends_at"] = json!(Utc::now() - chrono::Duration::days(1));
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(full_page),
+        );
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![expired, mock_entitlement(102, 99, 777)]),
+        );
+
+        let report = reconcile_all_entitlements(&state).await.unwrap();
+        assert_eq!(report.pages, 2);
+        assert_eq!(report.entitlements_seen, 102);
+        assert_eq!(report.entitlements_stored, 101);
+        assert_eq!(report.entitlements_skipped, 1);
+        assert_eq!(report.users_reconciled, 3);
+        assert_eq!(report.count(SubscriptionAction::Granted), 1);
+        assert_eq!(report.count(SubscriptionAction::Downgraded), 1);
+        assert_eq!(report.errors, 0);
+
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Premium);
+        assert_eq!(tier(&state, 2).await, SubscriptionTier::Free);
+        assert_eq!(tier(&state, 3).await, SubscriptionTier::Free);
+
+        let requests = discord.requests_to(MockEndpoint::Entitlements);
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].uri.contains("after=0&limit=100"));
+        assert!(requests[1].uri.contains("after=100&"));
+        assert!(requests[1].uri.contains("exclude_deleted=false"));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_all_entitlements_removes_unlisted_entitlements() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = discord.state_with_user(1).await;
+
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(1, 1, 777)]),
+        );
+        reconcile_all_entitlements(&state).await.unwrap();
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Premium);
+
+        // Refunded: Discord no longer lists it at all
+        let report = reconcile_all_entitlements(&state).await.unwrap();
+        assert_eq!(report.entitlements_deleted, 1);
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Free);
+        assert!(state
+            .storage
+            .get_user_entitlements(1)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_all_entitlements_keeps_entitlements_stored_during_run() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(discord.state_with_user(1).await);
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(1, 1, 777)]),
+        );
+        reconcile_all_entitlements(&state).await.unwrap();
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Premium);
+
+        // The user resubscribes while Discord is being paged, so the listing
+        // predates the new entitlement
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(Vec::new()).with_delay(Duration::from_millis(300)),
+        );
+        let run = tokio::spawn({
+            let state = state.clone();
+            async move { reconcile_all_entitlements(&state).await }
+        });
+        while discord.requests_to(MockEndpoint::Entitlements).len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let resubscribed = serde_json::from_value(mock_entitlement(2, 1, 777)).unwrap();
+        entitlements::store_entitlement(&state, &resubscribed)
+            .await
+            .unwrap();
+
+        let report = run.await.unwrap().unwrap();
+        assert_eq!(report.entitlements_deleted, 1);
+        let stored = state.storage.get_user_entitlements(1).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].entitlement_id, 2);
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Premium);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_all_entitlements_fails_before_deleting() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = discord.state_with_user(1).await;
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::entitlements(vec![mock_entitlement(1, 1, 777)]),
+        );
+        reconcile_all_entitlements(&state).await.unwrap();
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Premium);
+
+        // A failed listing must not look like Discord listing nothing
+        discord.enqueue(
+            MockEndpoint::Entitlements,
+            MockResponse::error(reqwest::StatusCode::INTERNAL_SERVER_ERROR),
+        );
+        assert!(reconcile_all_entitlements(&state).await.is_err());
+        let stored = state.storage.get_user_entitlements(1).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].entitlement_id, 1);
+        assert_eq!(tier(&state, 1).await, SubscriptionTier::Premium);
+    }
+}
@@ -0,0 +1,386 @@
+//! Admin routes for user and subscription management.
+//!
+//! Every route requires a permission through `RequirePermission`: reads need
+//! [`ReadUsers`] and writes need [`ManageUsers`]. The built-in `admin` role
+//! grants both.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::sessions::end_all_sessions;
+use crate::{
+    auth::{Permission, RequirePermission},
+    models::{
+        Entitlement, SubscriptionDecision, SubscriptionSource, SubscriptionTier, User,
+        UserListParams,
+    },
+    AppState,
+};
+
+/// Users returned by `GET /users` when no `limit` is given.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Largest `limit` accepted by `GET /users`.
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// Permission to search and view users.
+pub struct ReadUsers;
+
+impl Permission for ReadUsers {
+    const NAME: &'static str = "users:read";
+}
+
+/// Permission to change users' subscriptions, tokens and sessions.
+pub struct ManageUsers;
+
+impl Permission for ManageUsers {
+    const NAME: &'static str = "users:manage";
+}
+
+/// Create an Axum router with the admin routes.
+///
+/// Routes:
+/// - `GET /users` - Search and list users
+/// - `GET /users/{id}` - Get a user with their roles and entitlements
+/// - `POST /users/{id}/premium` - Grant premium, optionally until an expiry
+/// - `DELETE /users/{id}/premium` - Revoke premium
+/// - `DELETE /users/{id}/tokens` - Clear the user's stored Discord tokens
+/// - `POST /users/{id}/logout` - End every session of the user
+pub fn admin_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/users", get(list_users))
+        .route("/users/{id}", get(get_user))
+        .route(
+            "/users/{id}/premium",
+            post(grant_premium).delete(revoke_premium),
+        )
+        .route("/users/{id}/tokens", delete(clear_tokens))
+        .route("/users/{id}/logout", post(force_logout))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUsersQuery {
+    /// Substring of the username or display name, or an exact user ID.
+    pub q: Option<String>,
+    /// Only list users on this tier.
+    pub tier: Option<SubscriptionTier>,
+    /// Page size, at most 200 (default 50).
+    pub limit: Option<i64>,
+    /// Number of users to skip.
+    pub offset: Option<i64>,
+}
+
+impl ListUsersQuery {
+    fn params(&self) -> UserListParams<'_> {
+        UserListParams {
+            search: self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()),
+            tier: self.tier,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            offset: self.offset.unwrap_or_default().max(0),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GrantPremiumRequest {
+    /// When the grant ends; omit for a lifetime grant.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserResponse {
+    pub user_id: i64,
+    pub username: String,
+    pub global_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub granted_scopes: Vec<String>,
+    pub subscription_tier: SubscriptionTier,
+    pub subscription_source: Option<SubscriptionSource>,
+    pub subscription_expires_at: Option<DateTime<Utc>>,
+    pub is_premium: bool,
+    /// When the stored Discord grant expires; None if the user has no stored grant.
+    pub token_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<User> for AdminUserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            is_premium: user.is_premium(),
+            user_id: user.user_id,
+            username: user.username,
+            global_name: user.global_name,
+            avatar_url: user.avatar_url,
+            granted_scopes: user.granted_scopes,
+            subscription_tier: user.subscription_tier,
+            subscription_source: user.subscription_source,
+            subscription_expires_at: user.subscription_expires_at,
+            token_expires_at: user.token_expires_at,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserDetailResponse {
+    #[serde(flatten)]
+    pub user: AdminUserResponse,
+    pub roles: Vec<String>,
+    pub entitlements: Vec<Entitlement>,
+    /// Most recent entitlement reconciliation decisions, newest first.
+    pub subscription_decisions: Vec<SubscriptionDecision>,
+}
+
+/// How many reconciliation decisions the user detail includes.
+pub const RECENT_DECISIONS_LIMIT: i64 = 20;
+
+/// Search and list users, newest first.
+pub async fn list_users(
+    _guard: RequirePermission<ReadUsers>,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListUsersQuery>,
+) -> Result<Json<Vec<AdminUserResponse>>, StatusCode> {
+    let users = state
+        .storage
+        .list_users(query.params())
+        .await
+        .map_err(|e| {
+            tracing::error!("Storage error listing users: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(
+        users.into_iter().map(AdminUserResponse::from).collect(),
+    ))
+}
+
+/// Get a user with their roles, entitlements and recent reconciliation decisions.
+pub async fn get_user(
+    _guard: RequirePermission<ReadUsers>,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+) -> Result<Json<AdminUserDetailResponse>, StatusCode> {
+    let user = load_user(&state, user_id).await?;
+
+    let roles = state.storage.get_user_roles(user_id).await.map_err(|e| {
+        tracing::error!("Storage error fetching roles for {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let entitlements = state
+        .storage
+        .get_user_entitlements(user_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Storage error fetching entitlements for {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let subscription_decisions = state
+        .storage
+        .get_subscription_decisions(user_id, RECENT_DECISIONS_LIMIT)
+        .await
+        .map_err(|e| {
+            tracing::error!("Storage error fetching decisions for {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(AdminUserDetailResponse {
+        user: user.into(),
+        roles,
+        entitlements,
+        subscription_decisions,
+    }))
+}
+
+/// Grant a user premium as a manual subscription.
+///
+/// Replaces any existing subscription. Returns 400 if `expires_at` is in the past.
+pub async fn grant_premium(
+    guard: RequirePermission<ManageUsers>,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+    payload: Option<Json<GrantPremiumRequest>>,
+) -> Result<Json<AdminUserResponse>, StatusCode> {
+    let Json(request) = payload.unwrap_or_default();
+    if request
+        .expires_at
+        .is_some_and(|expires| expires <= Utc::now())
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    set_subscription(
+        &state,
+        user_id,
+        SubscriptionTier::Premium,
+        request.expires_at,
+    )
+    .await?;
+    tracing::info!(
+        "Admin {} granted premium to user {} (expires: {:?})",
+        guard.user.user_id,
+        user_id,
+        request.expires_at
+    );
+
+    Ok(Json(load_user(&state, user_id).await?.into()))
+}
+
+/// Revoke a user's premium, recording the change as a manual subscription.
+pub async fn revoke_premium(
+    guard: RequirePermission<ManageUsers>,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+) -> Result<Json<AdminUserResponse>, StatusCode> {
+    set_subscription(&state, user_id, SubscriptionTier::Free, None).await?;
+    tracing::info!(
+        "Admin {} revoked premium from user {}",
+        guard.user.user_id,
+        user_id
+    );
+
+    Ok(Json(load_user(&state, user_id).await?.into()))
+}
+
+/// Clear a user's stored Discord tokens without ending their sessions.
+pub async fn clear_tokens(
+    guard: RequirePermission<ManageUsers>,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    load_user(&state, user_id).await?;
+    state
+        .storage
+        .clear_user_tokens(user_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to clear tokens for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tracing::info!(
+        "Admin {} cleared Discord tokens of user {}",
+        guard.user.user_id,
+        user_id
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Log a user out of every session.
+///
+/// Revokes all sessions, refresh tokens and outstanding JWTs, clears the stored
+/// Discord tokens and closes the user's WebSocket and SSE connections.
+pub async fn force_logout(
+    guard: RequirePermission<ManageUsers>,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    load_user(&state, user_id).await?;
+    end_all_sessions(&state, user_id).await.map_err(|e| {
+        tracing::error!("Failed to log out user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tracing::info!(
+        "Admin {} logged out user {} everywhere",
+        guard.user.user_id,
+        user_id
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch a user, or 404.
+async fn load_user(state: &AppState, user_id: i64) -> Result<User, StatusCode> {
+    state
+        .storage
+        .get_user(user_id, &state.config.security.encryption_key)
+        .await
+        .map_err(|e| {
+            tracing::error!("Storage error fetching user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Set a user's tier as a manual subscription, or 404 if the user does not exist.
+async fn set_subscription(
+    state: &AppState,
+    user_id: i64,
+    tier: SubscriptionTier,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(), StatusCode> {
+    load_user(state, user_id).await?;
+    state
+        .storage
+        .update_subscription(user_id, tier, SubscriptionSource::Manual, expires_at)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to update subscription of user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_users_query_params() {
+        let query: ListUsersQuery =
+            serde_urlencoded::from_str("q=%20alice%20&tier=premium&limit=1000&offset=-5").unwrap();
+        let params = query.params();
+        assert_eq!(params.search, Some("alice"));
+        assert_eq!(params.tier, Some(SubscriptionTier::Premium));
+        assert_eq!(params.limit, MAX_PAGE_SIZE);
+        assert_eq!(params.offset, 0);
+
+        let query: ListUsersQuery = serde_urlencoded::from_str("q=").unwrap();
+        let params = query.params();
+        assert_eq!(params.search, None);
+        assert_eq!(params.limit, DEFAULT_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_admin_user_detail_serialization() {
+        let user = User {
+            user_id: 123,
+            username: "alice".to_string(),
+            global_name: None,
+            avatar_url: None,
+            refresh_token: Some("secret".to_string()),
+            token_expires_at: None,
+            granted_scopes: vec!["identify".to_string()],
+            token_version: 0,
+            subscription_tier: SubscriptionTier::Premium,
+            subscription_source: Some(SubscriptionSource::Manual),
+            subscription_expires_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let detail = AdminUserDetailResponse {
+            user: user.into(),
+            roles: vec!["admin".to_string()],
+            entitlements: vec![],
+            subscription_decisions: vec![],
+        };
+
+        let json = serde_json::to_value(&detail).unwrap();
+        assert_eq!(json["user_id"], 123);
+        assert_eq!(json["is_premium"], true);
+        assert_eq!(json["subscription_source"], "manual");
+        assert_eq!(json["roles"][0], "admin");
+        assert!(json.get("refresh_token").is_none());
+    }
+}
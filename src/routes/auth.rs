@@ -1,55 +1,141 @@
 //! Discord `OAuth2` authentication routes.
 //!
 //! This module provides HTTP handlers for:
+//! - Authorization request setup (PKCE + signed `state`)
 //! - Code exchange (`OAuth2` authorization code -> access token)
-//! - Token refresh
+//! - Server-side browser login (redirect + callback)
+//! - Token refresh (rotating first-party refresh tokens)
 //! - Token revocation
 //! - User info retrieval
-//! - Logout
+//! - Logout (current session or everywhere) and session listing
+//! - Account event streaming (WebSocket and Server-Sent Events)
 
 use std::sync::Arc;
 
 use axum::{
-    extract::State,
-    http::StatusCode,
-    routing::{get, post},
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Redirect,
+    routing::{delete, get, post},
     Json, Router,
 };
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+use super::{
+    events::event_stream,
+    sessions::{delete_session, end_all_sessions, end_session, list_sessions, logout_all},
+    ws::{issue_ws_ticket, ws_handler},
+};
 use crate::{
-    auth::{self, AuthenticatedUser},
-    models::{EntitlementUpsertParams, SubscriptionSource, SubscriptionTier, UserUpsertParams},
-    AppState,
+    auth::{self, AuthenticatedUser, Claims, ClientInfo},
+    cookies,
+    discord::{DiscordError, DiscordTokenResponse, DiscordUser},
+    entitlements,
+    models::{
+        RefreshTokenParams, RefreshTokenStatus, SessionCreateParams, SubscriptionTier,
+        UserUpsertParams,
+    },
+    oauth, AppState,
 };
 
 /// Create an Axum router with all auth routes.
 ///
 /// Routes:
+/// - `POST /authorize` - Generate a PKCE challenge and signed `state`
 /// - `POST /exchange` - Exchange authorization code for tokens
-/// - `POST /refresh` - Refresh the OAuth token
-/// - `POST /revoke` - Revoke tokens with Discord
-/// - `POST /logout` - Clear local tokens
+/// - `GET /login` - Redirect the browser to Discord's authorize page
+/// - `GET /callback` - Complete a browser login and redirect to the app
+/// - `POST /refresh` - Rotate a refresh token for a new access token
+/// - `POST /revoke` - Revoke tokens with Discord and end every session
+/// - `POST /logout` - End the current session
+/// - `POST /logout-all` - End every session
+/// - `GET /sessions` - List the user's active sessions
+/// - `DELETE /sessions/{id}` - End one session
+/// - `POST /ws-ticket` - Issue a single-use WebSocket or event stream ticket
+/// - `GET /ws` - Open a WebSocket authenticated by a ticket
+/// - `GET /events` - Stream account events as Server-Sent Events
 /// - `GET /me` - Get current user info
 pub fn auth_router() -> Router<Arc<AppState>> {
     Router::new()
+        .route("/authorize", post(authorize))
         .route("/exchange", post(exchange_code))
+        .route("/login", get(login))
+        .route("/callback", get(callback))
         .route("/refresh", post(refresh_token))
         .route("/revoke", post(revoke_token))
         .route("/logout", post(logout))
+        .route("/logout-all", post(logout_all))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/{id}", delete(delete_session))
+        .route("/ws-ticket", post(issue_ws_ticket))
+        .route("/ws", get(ws_handler))
+        .route("/events", get(event_stream))
         .route("/me", get(get_current_user))
 }
 
+/// Cookie holding the PKCE code verifier between `/login` and `/callback`.
+const PKCE_VERIFIER_COOKIE: &str = "catacombs_pkce_verifier";
+
+/// `/refresh` renews the stored Discord grant once it is this close to expiring.
+const DISCORD_REFRESH_WINDOW_HOURS: i64 = 24;
+
 #[derive(Debug, Deserialize)]
 pub struct CodeExchangeRequest {
     pub code: String,
+    /// Signed `state` returned by `/authorize` and echoed back by Discord.
+    #[serde(default)]
+    pub state: Option<String>,
+    /// PKCE code verifier returned by `/authorize`.
+    #[serde(default)]
+    pub code_verifier: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeResponse {
+    /// Signed `state` to pass to Discord's authorize endpoint.
+    pub state: String,
+    /// PKCE code challenge to pass to Discord's authorize endpoint.
+    pub code_challenge: String,
+    /// PKCE code challenge method (always `S256`).
+    pub code_challenge_method: &'static str,
+    /// PKCE code verifier; keep it client-side and send it to `/exchange`.
+    pub code_verifier: String,
+    /// Space-separated `OAuth2` scopes to request, from `DiscordConfig::scopes`.
+    pub scope: String,
+}
+
+/// Query parameters Discord appends when redirecting back to `/callback`.
+#[derive(Debug, Deserialize)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Error code when the user denied access or the request was invalid.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    /// Opaque refresh token from the previous `TokenResponse`.
+    pub refresh_token: String,
 }
 
 #[derive(Debug, Serialize)]
 pub struct TokenResponse {
-    /// JWT token for backend API authentication.
+    /// JWT token for backend API authentication; omitted in cookie session mode.
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub access_token: String,
+    /// Lifetime of `access_token`, in seconds.
+    pub expires_in: i64,
+    /// Opaque first-party refresh token; single use, exchange it at `/refresh`.
+    /// Omitted in cookie session mode.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub refresh_token: String,
+    /// CSRF token to send as `X-CSRF-Token`; only set in cookie session mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
     /// Discord OAuth access token for Discord SDK authentication.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub discord_access_token: Option<String>,
@@ -63,68 +149,212 @@ pub struct UserResponse {
     pub avatar_url: Option<String>,
     pub subscription_tier: SubscriptionTier,
     pub is_premium: bool,
+    pub granted_scopes: Vec<String>,
 }
 
-/// Discord user response from /users/@me endpoint.
-#[derive(Debug, Deserialize)]
-struct DiscordUser {
-    id: String,
-    username: String,
-    avatar: Option<String>,
-    global_name: Option<String>,
-    discriminator: Option<String>,
-}
-
-/// Discord `OAuth2` token response.
-#[derive(Debug, Deserialize)]
-struct DiscordTokenResponse {
-    access_token: String,
-    #[allow(dead_code)]
-    token_type: String,
-    expires_in: i64,
-    refresh_token: String,
-    #[allow(dead_code)]
-    scope: String,
-}
+/// Generate a PKCE challenge and a signed `state` for a new authorization request.
+pub async fn authorize(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<AuthorizeResponse>, StatusCode> {
+    let request = oauth::begin_authorization(&state.config.security.jwt_secret).map_err(|e| {
+        tracing::error!("Failed to create authorization request: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
-/// Discord entitlement from the API.
-#[derive(Debug, Deserialize)]
-struct DiscordEntitlementResponse {
-    id: String,
-    sku_id: String,
-    #[allow(dead_code)]
-    user_id: Option<String>,
-    #[serde(rename = "type")]
-    entitlement_type: i32,
-    #[serde(default)]
-    deleted: bool,
-    starts_at: Option<DateTime<Utc>>,
-    ends_at: Option<DateTime<Utc>>,
-    #[serde(default)]
-    consumed: bool,
+    Ok(Json(AuthorizeResponse {
+        state: request.state,
+        code_challenge: request.code_challenge,
+        code_challenge_method: "S256",
+        code_verifier: request.code_verifier,
+        scope: state.config.discord.scopes.join(" "),
+    }))
 }
 
 /// Exchange Discord authorization code for access token and create user session.
+///
+/// If the request carries a `state` and `code_verifier` from `/authorize`, both
+/// are verified before the code is sent to Discord, and the state is redeemed
+/// so it cannot be used twice. Requests without them are
+/// rejected when `SecurityConfig::require_pkce` is set. In cookie session mode
+/// the JWT and refresh token are set as cookies instead of returned. Answers
+/// 429 when Discord keeps rate limiting the exchange.
 pub async fn exchange_code(
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    jar: CookieJar,
     Json(payload): Json<CodeExchangeRequest>,
-) -> Result<Json<TokenResponse>, StatusCode> {
+) -> Result<(CookieJar, Json<TokenResponse>), StatusCode> {
     tracing::info!("Exchanging authorization code for access token");
 
+    // Verify the signed state and PKCE verifier before talking to Discord
+    match (&payload.state, &payload.code_verifier) {
+        (Some(oauth_state), Some(code_verifier)) => {
+            redeem_state(&state, oauth_state, code_verifier).await?;
+        }
+        (None, None) if !state.config.security.require_pkce => {}
+        _ => {
+            tracing::warn!("Rejected code exchange: missing state or code verifier");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
     // Exchange authorization code for Discord access token
-    let discord_token = exchange_code_with_discord(&state, &payload.code)
+    let discord_token = state
+        .discord
+        .exchange_code(&payload.code, payload.code_verifier.as_deref())
         .await
         .map_err(|e| {
             tracing::error!("Failed to exchange code with Discord: {}", e);
-            StatusCode::UNAUTHORIZED
+            discord_error_status(&e, StatusCode::UNAUTHORIZED)
+        })?;
+
+    let tokens = complete_login(&state, &discord_token, &client).await?;
+    let (jar, tokens) = deliver_tokens(&state, jar, tokens);
+    Ok((jar, Json(tokens)))
+}
+
+/// Verify a signed `state` against its PKCE verifier and redeem it.
+///
+/// Returns 401 if the state does not verify or was already redeemed, so a
+/// leaked state cannot be replayed within its TTL.
+async fn redeem_state(
+    state: &AppState,
+    oauth_state: &str,
+    code_verifier: &str,
+) -> Result<(), StatusCode> {
+    let verified = oauth::verify_state(
+        oauth_state,
+        code_verifier,
+        &state.config.security.jwt_secret,
+    )
+    .map_err(|e| {
+        tracing::warn!("Rejected authorization state: {}", e);
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    let first_use = state
+        .storage
+        .consume_oauth_state(&verified.nonce, verified.expires_at)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to redeem authorization state: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !first_use {
+        tracing::warn!("Rejected authorization state: already redeemed");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(())
+}
+
+/// Start a browser login by redirecting to Discord's authorize page.
+///
+/// The PKCE code verifier is kept in an `HttpOnly` cookie until `/callback`.
+pub async fn login(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Redirect), StatusCode> {
+    let request = oauth::begin_authorization(&state.config.security.jwt_secret).map_err(|e| {
+        tracing::error!("Failed to create authorization request: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let url = oauth::authorize_url(&state.config.discord, &request).map_err(|e| {
+        tracing::error!("Failed to build Discord authorize URL: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let cookie = Cookie::build((PKCE_VERIFIER_COOKIE, request.code_verifier))
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .build();
+
+    Ok((jar.add(cookie), Redirect::to(url.as_str())))
+}
+
+/// Complete a browser login started by `/login`.
+///
+/// Verifies the signed `state` against the PKCE verifier cookie, exchanges the
+/// code and redirects to `DiscordConfig::post_login_redirect_uri` with the JWT
+/// and refresh token in the URL fragment
+/// (`#access_token=...&token_type=Bearer&expires_in=...&refresh_token=...`),
+/// so they never reach server logs. In cookie session mode the tokens are set
+/// as cookies and the redirect carries no fragment. Errors are reported as
+/// `#error=...`.
+pub async fn callback(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Query(params): Query<CallbackParams>,
+) -> Result<(CookieJar, Redirect), StatusCode> {
+    let code_verifier = jar.get(PKCE_VERIFIER_COOKIE).map(|c| c.value().to_string());
+    let jar = jar.remove(Cookie::build(PKCE_VERIFIER_COOKIE).path("/"));
+    let post_login = &state.config.discord.post_login_redirect_uri;
+
+    if let Some(error) = params.error {
+        tracing::warn!("Discord authorization failed: {}", error);
+        return Ok((jar, post_login_redirect(post_login, &[("error", &error)])));
+    }
+
+    let (Some(code), Some(oauth_state), Some(code_verifier)) =
+        (params.code, params.state, code_verifier)
+    else {
+        tracing::warn!("Rejected login callback: missing code, state or verifier cookie");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    redeem_state(&state, &oauth_state, &code_verifier).await?;
+
+    let discord_token = state
+        .discord
+        .exchange_code(&code, Some(&code_verifier))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to exchange code with Discord: {}", e);
+            discord_error_status(&e, StatusCode::UNAUTHORIZED)
         })?;
 
+    let tokens = complete_login(&state, &discord_token, &client).await?;
+    if state.config.security.cookie_sessions {
+        let (jar, _) = deliver_tokens(&state, jar, tokens);
+        return Ok((jar, Redirect::to(post_login)));
+    }
+    let expires_in = tokens.expires_in.to_string();
+
+    Ok((
+        jar,
+        post_login_redirect(
+            post_login,
+            &[
+                ("access_token", &tokens.access_token),
+                ("token_type", "Bearer"),
+                ("expires_in", &expires_in),
+                ("refresh_token", &tokens.refresh_token),
+            ],
+        ),
+    ))
+}
+
+/// Finish a login after a successful Discord code exchange.
+///
+/// Fetches the Discord user, creates or updates the stored user, refreshes
+/// premium status from entitlements and starts a new session with a JWT and a
+/// refresh token.
+async fn complete_login(
+    state: &AppState,
+    discord_token: &DiscordTokenResponse,
+    client: &ClientInfo,
+) -> Result<TokenResponse, StatusCode> {
     // Get user info from Discord API
-    let discord_user = get_discord_user_info(&discord_token.access_token, &state.http_client)
+    let discord_user = state
+        .discord
+        .current_user(&discord_token.access_token)
         .await
         .map_err(|e| {
             tracing::error!("Failed to get Discord user info: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            discord_error_status(&e, StatusCode::INTERNAL_SERVER_ERROR)
         })?;
 
     // Parse Discord user ID (u64 snowflake stored as i64)
@@ -138,6 +368,7 @@ pub async fn exchange_code(
 
     // Calculate token expiration
     let token_expires_at = Utc::now() + chrono::Duration::seconds(discord_token.expires_in);
+    let granted_scopes = discord_token.granted_scopes();
 
     // Create or update user in storage
     state
@@ -150,6 +381,7 @@ pub async fn exchange_code(
                 avatar_url: Some(&avatar_url.clone()),
                 refresh_token: Some(&discord_token.refresh_token),
                 token_expires_at: Some(token_expires_at),
+                granted_scopes: Some(&granted_scopes),
             },
             &state.config.security.encryption_key,
         )
@@ -161,15 +393,8 @@ pub async fn exchange_code(
 
     // Fetch and process user entitlements for premium status
     if state.config.discord.premium_sku_id.is_some() {
-        match fetch_user_entitlements(&state, user_id).await {
-            Ok(entitlements) => {
-                if let Err(e) = process_user_entitlements(&state, user_id, entitlements).await {
-                    tracing::warn!("Failed to process entitlements for user {}: {}", user_id, e);
-                }
-            }
-            Err(e) => {
-                tracing::warn!("Failed to fetch entitlements for user {}: {}", user_id, e);
-            }
+        if let Err(e) = entitlements::sync_user_entitlements(state, user_id).await {
+            tracing::warn!("Failed to sync entitlements for user {}: {}", user_id, e);
         }
     }
 
@@ -179,106 +404,261 @@ pub async fn exchange_code(
         user_id
     );
 
-    // Generate JWT token
-    let jwt_token = auth::generate_token(
+    let token_version = state
+        .storage
+        .get_token_version(user_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch token version: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .unwrap_or_default();
+    let roles = user_roles(state, user_id).await?;
+
+    // The session is keyed by the jti of its first JWT
+    let session_id = Uuid::new_v4();
+    let mut claims = Claims::new(
         user_id,
         &discord_user.username,
-        &state.config.security.jwt_secret,
+        &granted_scopes,
+        &state.config.security,
     )
-    .map_err(|e| {
+    .with_roles(&roles)
+    .with_session(session_id)
+    .with_token_version(token_version);
+    claims.jti = session_id.to_string();
+
+    state
+        .storage
+        .create_session(SessionCreateParams {
+            session_id,
+            user_id,
+            user_agent: client.user_agent.as_deref(),
+            ip_address: client.ip_address.as_deref(),
+            expires_at: refresh_token_expiry(state),
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut tokens = issue_tokens(state, &claims, session_id).await?;
+    tokens.discord_access_token = Some(discord_token.access_token.clone());
+    Ok(tokens)
+}
+
+/// Sign claims for a session and issue a refresh token in its token family.
+async fn issue_tokens(
+    state: &AppState,
+    claims: &Claims,
+    session_id: Uuid,
+) -> Result<TokenResponse, StatusCode> {
+    let security = &state.config.security;
+    let user_id = claims.sub.parse::<i64>().map_err(|e| {
+        tracing::error!("Failed to parse user ID from claims: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let access_token = state.jwt_keys.encode(claims).map_err(|e| {
         tracing::error!("Failed to generate JWT token: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    Ok(Json(TokenResponse {
-        access_token: jwt_token,
-        discord_access_token: Some(discord_token.access_token),
-    }))
+    let refresh_token = auth::generate_refresh_token();
+    state
+        .storage
+        .store_refresh_token(RefreshTokenParams {
+            token_hash: &auth::hash_refresh_token(&refresh_token),
+            user_id,
+            family_id: session_id,
+            expires_at: refresh_token_expiry(state),
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to store refresh token: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(TokenResponse {
+        access_token,
+        expires_in: security.jwt_ttl_seconds,
+        refresh_token,
+        csrf_token: None,
+        discord_access_token: None,
+    })
+}
+
+/// Move the JWT and refresh token into cookies when cookie sessions are enabled.
+///
+/// The tokens are dropped from the response body and a fresh CSRF token is
+/// issued in their place.
+fn deliver_tokens(
+    state: &AppState,
+    jar: CookieJar,
+    mut tokens: TokenResponse,
+) -> (CookieJar, TokenResponse) {
+    let security = &state.config.security;
+    if !security.cookie_sessions {
+        return (jar, tokens);
+    }
+
+    let csrf_token = cookies::generate_csrf_token();
+    let jar = cookies::set_session_cookies(
+        jar,
+        &tokens.access_token,
+        &tokens.refresh_token,
+        &csrf_token,
+        security,
+    );
+    tokens.access_token.clear();
+    tokens.refresh_token.clear();
+    tokens.csrf_token = Some(csrf_token);
+    (jar, tokens)
+}
+
+/// Expiry for a refresh token (and its session) issued now.
+fn refresh_token_expiry(state: &AppState) -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(state.config.security.refresh_token_ttl_seconds)
+}
+
+/// Fetch the roles to embed in a user's next JWT.
+async fn user_roles(state: &AppState, user_id: i64) -> Result<Vec<String>, StatusCode> {
+    state.storage.get_user_roles(user_id).await.map_err(|e| {
+        tracing::error!("Failed to fetch roles for user {}: {}", user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
 }
 
-/// Refresh the user's OAuth tokens and return a new JWT.
+/// Exchange a refresh token for a new JWT and a new refresh token.
+///
+/// Refresh tokens are single use. Presenting one that was already rotated means
+/// it has leaked, so its session and every token in its family are revoked and
+/// the user has to log in again. When the stored Discord grant is close to expiring it is
+/// refreshed too, and the new Discord access token is included in the response.
+///
+/// In cookie session mode the body may be omitted, in which case the refresh
+/// token is read from its cookie and a matching CSRF token is required.
 pub async fn refresh_token(
-    user: AuthenticatedUser,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<TokenResponse>, StatusCode> {
-    tracing::info!(
-        "Refreshing token for user: {} ({})",
-        user.username,
-        user.user_id
-    );
+    jar: CookieJar,
+    headers: HeaderMap,
+    payload: Option<Json<RefreshRequest>>,
+) -> Result<(CookieJar, Json<TokenResponse>), StatusCode> {
+    let refresh_token = match payload {
+        Some(Json(payload)) => payload.refresh_token,
+        None if state.config.security.cookie_sessions => {
+            let token = jar
+                .get(cookies::REFRESH_TOKEN_COOKIE)
+                .map(|c| c.value().to_string())
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            if !cookies::verify_csrf(&jar, &headers) {
+                tracing::warn!("Rejected cookie refresh without a valid CSRF token");
+                return Err(StatusCode::FORBIDDEN);
+            }
+            token
+        }
+        None => return Err(StatusCode::BAD_REQUEST),
+    };
 
-    // Get user with refresh token from storage
-    let db_user = state
+    let token_hash = auth::hash_refresh_token(&refresh_token);
+    let status = state
         .storage
-        .get_user(user.user_id, &state.config.security.encryption_key)
+        .consume_refresh_token(&token_hash)
         .await
         .map_err(|e| {
-            tracing::error!("Storage error fetching user for refresh: {}", e);
+            tracing::error!("Storage error consuming refresh token: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or_else(|| {
-            tracing::warn!("User not found for token refresh: {}", user.user_id);
-            StatusCode::NOT_FOUND
         })?;
 
-    let current_refresh_token = db_user.refresh_token.ok_or_else(|| {
-        tracing::warn!("No refresh token stored for user: {}", user.user_id);
-        StatusCode::UNAUTHORIZED
-    })?;
+    let (user_id, family_id) = match status {
+        RefreshTokenStatus::Valid { user_id, family_id } => (user_id, family_id),
+        RefreshTokenStatus::Reused { user_id, family_id } => {
+            tracing::warn!(
+                "Refresh token reuse detected for user {}, revoking session {}",
+                user_id,
+                family_id
+            );
+            end_session(&state, user_id, family_id).await.map_err(|e| {
+                tracing::error!("Failed to revoke refresh token family: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        RefreshTokenStatus::Invalid => {
+            tracing::warn!("Rejected unknown, expired or revoked refresh token");
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    tracing::info!("Refreshing token for user: {}", user_id);
 
-    // Refresh with Discord
-    let discord_token = refresh_discord_token(&state, &current_refresh_token)
+    let db_user = state
+        .storage
+        .get_user(user_id, &state.config.security.encryption_key)
         .await
         .map_err(|e| {
-            tracing::error!("Failed to refresh Discord token: {}", e);
+            tracing::error!("Storage error fetching user for refresh: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            tracing::warn!("User not found for token refresh: {}", user_id);
             StatusCode::UNAUTHORIZED
         })?;
 
-    let token_expires_at = Utc::now() + chrono::Duration::seconds(discord_token.expires_in);
+    let roles = user_roles(&state, user_id).await?;
+    let claims = Claims::new(
+        user_id,
+        &db_user.username,
+        &db_user.granted_scopes,
+        &state.config.security,
+    )
+    .with_roles(&roles)
+    .with_session(family_id)
+    .with_token_version(db_user.token_version);
+    let mut tokens = issue_tokens(&state, &claims, family_id).await?;
 
-    // Store new refresh token
-    state
+    if let Err(e) = state
         .storage
-        .update_refresh_token(
-            user.user_id,
-            &discord_token.refresh_token,
-            token_expires_at,
-            &state.config.security.encryption_key,
-        )
+        .touch_session(family_id, Some(refresh_token_expiry(&state)))
         .await
-        .map_err(|e| {
-            tracing::error!("Failed to update refresh token in storage: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    {
+        tracing::warn!("Failed to extend session {}: {}", family_id, e);
+    }
+
+    // Keep the stored Discord grant alive; failing to do so does not end the session
+    if let Some(discord_refresh_token) = &db_user.refresh_token {
+        let expires_soon = db_user.token_expires_at.map_or(true, |expires| {
+            expires - Utc::now() < chrono::Duration::hours(DISCORD_REFRESH_WINDOW_HOURS)
+        });
+        if expires_soon {
+            match renew_discord_grant(&state, user_id, discord_refresh_token).await {
+                Ok(access_token) => tokens.discord_access_token = Some(access_token),
+                Err(e) => tracing::warn!(
+                    "Failed to refresh Discord token for user {}: {}",
+                    user_id,
+                    e
+                ),
+            }
+        }
+    }
 
     tracing::info!(
         "Successfully refreshed token for user: {} ({})",
-        user.username,
-        user.user_id
+        db_user.username,
+        user_id
     );
-
-    // Generate new JWT
-    let jwt_token = auth::generate_token(
-        user.user_id,
-        &user.username,
-        &state.config.security.jwt_secret,
-    )
-    .map_err(|e| {
-        tracing::error!("Failed to generate JWT token: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    Ok(Json(TokenResponse {
-        access_token: jwt_token,
-        discord_access_token: Some(discord_token.access_token),
-    }))
+    let (jar, tokens) = deliver_tokens(&state, jar, tokens);
+    Ok((jar, Json(tokens)))
 }
 
-/// Revoke the user's Discord OAuth tokens and clear from storage.
+/// Revoke the user's Discord OAuth tokens, clear them from storage and end
+/// every session.
 pub async fn revoke_token(
     user: AuthenticatedUser,
     State(state): State<Arc<AppState>>,
-) -> Result<StatusCode, StatusCode> {
+    jar: CookieJar,
+) -> Result<(CookieJar, StatusCode), StatusCode> {
     tracing::info!(
         "Revoking tokens for user: {} ({})",
         user.username,
@@ -298,7 +678,7 @@ pub async fn revoke_token(
     // Revoke with Discord if we have a refresh token
     if let Some(user_data) = db_user {
         if let Some(refresh_token) = user_data.refresh_token {
-            if let Err(e) = revoke_discord_token(&state, &refresh_token).await {
+            if let Err(e) = state.discord.revoke_token(&refresh_token).await {
                 tracing::warn!(
                     "Failed to revoke token with Discord (continuing anyway): {}",
                     e
@@ -308,45 +688,46 @@ pub async fn revoke_token(
     }
 
     // Clear tokens from storage
-    state
-        .storage
-        .clear_user_tokens(user.user_id)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to clear tokens from storage: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    end_all_sessions(&state, user.user_id).await.map_err(|e| {
+        tracing::error!("Failed to clear tokens from storage: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
     tracing::info!(
         "Successfully revoked tokens for user: {} ({})",
         user.username,
         user.user_id
     );
-    Ok(StatusCode::NO_CONTENT)
+    let jar = cookies::clear_session_cookies(jar, &state.config.security);
+    Ok((jar, StatusCode::NO_CONTENT))
 }
 
-/// Log out the user by clearing their stored tokens.
+/// Log out the current session, revoking its JWTs and refresh tokens.
+///
+/// Tokens that are not bound to a session log the user out everywhere instead.
 pub async fn logout(
     user: AuthenticatedUser,
     State(state): State<Arc<AppState>>,
-) -> Result<StatusCode, StatusCode> {
+    jar: CookieJar,
+) -> Result<(CookieJar, StatusCode), StatusCode> {
     tracing::info!("Logging out user: {} ({})", user.username, user.user_id);
 
-    state
-        .storage
-        .clear_user_tokens(user.user_id)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to clear tokens for logout: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    let result = match user.session_id {
+        Some(session_id) => end_session(&state, user.user_id, session_id).await,
+        None => end_all_sessions(&state, user.user_id).await,
+    };
+    result.map_err(|e| {
+        tracing::error!("Failed to clear tokens for logout: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
     tracing::info!(
         "Successfully logged out user: {} ({})",
         user.username,
         user.user_id
     );
-    Ok(StatusCode::NO_CONTENT)
+    let jar = cookies::clear_session_cookies(jar, &state.config.security);
+    Ok((jar, StatusCode::NO_CONTENT))
 }
 
 /// Get current user info from storage.
@@ -381,13 +762,51 @@ pub async fn get_current_user(
         avatar_url: db_user.avatar_url,
         subscription_tier: db_user.subscription_tier,
         is_premium,
+        granted_scopes: db_user.granted_scopes,
     }))
 }
 
+/// Refresh the stored Discord grant and return the new Discord access token.
+async fn renew_discord_grant(
+    state: &AppState,
+    user_id: i64,
+    refresh_token: &str,
+) -> anyhow::Result<String> {
+    let discord_token = state.discord.refresh_token(refresh_token).await?;
+    let token_expires_at = Utc::now() + chrono::Duration::seconds(discord_token.expires_in);
+
+    state
+        .storage
+        .update_refresh_token(
+            user_id,
+            &discord_token.refresh_token,
+            token_expires_at,
+            &state.config.security.encryption_key,
+        )
+        .await?;
+
+    Ok(discord_token.access_token)
+}
+
 // ============================================================================
 // Discord API helpers
 // ============================================================================
 
+/// Status to answer a failed Discord call with: 429 if Discord rate limited it
+/// past the retry budget, `fallback` otherwise.
+fn discord_error_status(error: &DiscordError, fallback: StatusCode) -> StatusCode {
+    match error {
+        DiscordError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        _ => fallback,
+    }
+}
+
+/// Redirect to the post-login URL with the given parameters in the fragment.
+fn post_login_redirect(base: &str, params: &[(&str, &str)]) -> Redirect {
+    let fragment = serde_urlencoded::to_string(params).unwrap_or_default();
+    Redirect::to(&format!("{base}#{fragment}"))
+}
+
 /// Build a CDN URL for a Discord user's avatar (or the default embed avatar).
 fn build_avatar_url(user: &DiscordUser) -> String {
     if let Some(avatar_hash) = user.avatar.as_ref() {
@@ -410,257 +829,14 @@ fn build_avatar_url(user: &DiscordUser) -> String {
     }
 }
 
-async fn exchange_code_with_discord(
-    state: &AppState,
-    code: &str,
-) -> anyhow::Result<DiscordTokenResponse> {
-    let params = [
-        ("grant_type", "authorization_code"),
-        ("code", code),
-        ("redirect_uri", state.config.discord.redirect_uri.as_str()),
-    ];
-
-    let response = state
-        .http_client
-        .post("https://discord.com/api/v10/oauth2/token")
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .basic_auth(
-            &state.config.discord.client_id,
-            Some(&state.config.discord.client_secret),
-        )
-        .form(&params)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        tracing::error!("Discord token exchange failed: {} - {}", status, error_text);
-        anyhow::bail!("Discord token exchange failed with status {status}");
-    }
-
-    Ok(response.json::<DiscordTokenResponse>().await?)
-}
-
-async fn get_discord_user_info(
-    access_token: &str,
-    http_client: &reqwest::Client,
-) -> anyhow::Result<DiscordUser> {
-    let response = http_client
-        .get("https://discord.com/api/v10/users/@me")
-        .header("Authorization", format!("Bearer {access_token}"))
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        tracing::error!(
-            "Discord user info fetch failed: {} - {}",
-            status,
-            error_text
-        );
-        anyhow::bail!("Failed to fetch Discord user info with status {status}");
-    }
-
-    Ok(response.json::<DiscordUser>().await?)
-}
-
-async fn refresh_discord_token(
-    state: &AppState,
-    refresh_token: &str,
-) -> anyhow::Result<DiscordTokenResponse> {
-    let params = [
-        ("grant_type", "refresh_token"),
-        ("refresh_token", refresh_token),
-    ];
-
-    let response = state
-        .http_client
-        .post("https://discord.com/api/v10/oauth2/token")
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .basic_auth(
-            &state.config.discord.client_id,
-            Some(&state.config.discord.client_secret),
-        )
-        .form(&params)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        tracing::error!("Discord token refresh failed: {} - {}", status, error_text);
-        anyhow::bail!("Discord token refresh failed with status {status}");
-    }
-
-    Ok(response.json::<DiscordTokenResponse>().await?)
-}
-
-async fn revoke_discord_token(state: &AppState, token: &str) -> anyhow::Result<()> {
-    let params = [("token", token)];
-
-    let response = state
-        .http_client
-        .post("https://discord.com/api/v10/oauth2/token/revoke")
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .basic_auth(
-            &state.config.discord.client_id,
-            Some(&state.config.discord.client_secret),
-        )
-        .form(&params)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        tracing::warn!(
-            "Discord token revocation returned non-success: {} - {}",
-            status,
-            error_text
-        );
-    }
-
-    Ok(())
-}
-
-async fn fetch_user_entitlements(
-    state: &AppState,
-    user_id: i64,
-) -> anyhow::Result<Vec<DiscordEntitlementResponse>> {
-    let user_id_str = user_id.to_string();
-    let url = format!(
-        "https://discord.com/api/v10/applications/{}/entitlements?user_id={}&exclude_ended=false",
-        state.config.discord.client_id, user_id_str
-    );
-
-    let response = state
-        .http_client
-        .get(&url)
-        .header(
-            "Authorization",
-            format!("Bot {}", state.config.discord.bot_token),
-        )
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        tracing::warn!(
-            "Discord entitlements fetch failed: {} - {}",
-            status,
-            error_text
-        );
-        return Ok(vec![]);
-    }
-
-    Ok(response.json::<Vec<DiscordEntitlementResponse>>().await?)
-}
-
-async fn process_user_entitlements(
-    state: &AppState,
-    user_id: i64,
-    entitlements: Vec<DiscordEntitlementResponse>,
-) -> anyhow::Result<SubscriptionTier> {
-    let premium_sku_id = state.config.discord.premium_sku_id;
-
-    let mut highest_tier = SubscriptionTier::Free;
-    let mut subscription_expires: Option<DateTime<Utc>> = None;
-
-    for entitlement in entitlements {
-        if entitlement.deleted {
-            continue;
-        }
-
-        let ent_id: i64 = match entitlement.id.parse() {
-            Ok(id) => id,
-            Err(e) => {
-                tracing::warn!("Failed to parse entitlement.id '{}': {}", entitlement.id, e);
-                continue;
-            }
-        };
-        let sku_id: i64 = match entitlement.sku_id.parse() {
-            Ok(id) => id,
-            Err(e) => {
-                tracing::warn!(
-                    "Failed to parse entitlement.sku_id '{}': {}",
-                    entitlement.sku_id,
-                    e
-                );
-                continue;
-            }
-        };
-
-        // Store entitlement
-        if let Err(e) = state
-            .storage
-            .upsert_entitlement(EntitlementUpsertParams {
-                entitlement_id: ent_id,
-                user_id,
-                sku_id,
-                entitlement_type: entitlement.entitlement_type,
-                is_test: false,
-                consumed: entitlement.consumed,
-                starts_at: entitlement.starts_at,
-                ends_at: entitlement.ends_at,
-            })
-            .await
-        {
-            tracing::warn!("Failed to upsert entitlement {}: {}", ent_id, e);
-            continue;
-        }
-
-        // Check if this entitlement grants premium
-        if let Some(premium_sku) = premium_sku_id {
-            if sku_id == premium_sku {
-                let is_active = match entitlement.ends_at {
-                    Some(ends) => ends > Utc::now(),
-                    None => true,
-                };
-
-                if is_active {
-                    highest_tier = SubscriptionTier::Premium;
-                    match (subscription_expires, entitlement.ends_at) {
-                        (None, ends) => subscription_expires = ends,
-                        (Some(current), Some(ends)) if ends > current => {
-                            subscription_expires = Some(ends);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-        }
-    }
-
-    // Update user's subscription tier
-    if highest_tier != SubscriptionTier::Free {
-        state
-            .storage
-            .update_subscription(
-                user_id,
-                highest_tier,
-                SubscriptionSource::Discord,
-                subscription_expires,
-            )
-            .await?;
-        tracing::info!(
-            "Updated user {} subscription to {:?} (expires: {:?})",
-            user_id,
-            highest_tier,
-            subscription_expires
-        );
-    }
-
-    Ok(highest_tier)
-}
-
 #[cfg(test)]
 mod tests {
+    use axum::{http::header, response::IntoResponse};
+
     use super::{
-        build_avatar_url, CodeExchangeRequest, DiscordUser, SubscriptionTier, TokenResponse,
-        UserResponse,
+        build_avatar_url, discord_error_status, post_login_redirect, AuthorizeResponse,
+        CallbackParams, CodeExchangeRequest, DiscordError, DiscordTokenResponse, DiscordUser,
+        RefreshRequest, StatusCode, SubscriptionTier, TokenResponse, UserResponse,
     };
 
     /// Helper function to create a `DiscordUser` for testing.
@@ -696,17 +872,105 @@ mod tests {
         let json = r#"{"code": "test_auth_code_12345"}"#;
         let request: CodeExchangeRequest = serde_json::from_str(json).unwrap();
         assert_eq!(request.code, "test_auth_code_12345");
+        assert!(request.state.is_none());
+        assert!(request.code_verifier.is_none());
+    }
+
+    #[test]
+    fn test_code_exchange_request_with_pkce_deserialization() {
+        let json = r#"{"code": "abc", "state": "payload.sig", "code_verifier": "verifier"}"#;
+        let request: CodeExchangeRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.state.as_deref(), Some("payload.sig"));
+        assert_eq!(request.code_verifier.as_deref(), Some("verifier"));
+    }
+
+    #[test]
+    fn test_authorize_response_serialization() {
+        let response = AuthorizeResponse {
+            state: "payload.sig".to_string(),
+            code_challenge: "challenge".to_string(),
+            code_challenge_method: "S256",
+            code_verifier: "verifier".to_string(),
+            scope: "identify email".to_string(),
+        };
+
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(json.contains(r#""code_challenge_method":"S256""#));
+        assert!(json.contains("code_verifier"));
+    }
+
+    #[test]
+    fn test_refresh_request_deserialization() {
+        let request: RefreshRequest =
+            serde_json::from_str(r#"{"refresh_token": "opaque"}"#).unwrap();
+        assert_eq!(request.refresh_token, "opaque");
+        assert!(serde_json::from_str::<RefreshRequest>("{}").is_err());
+    }
+
+    #[test]
+    fn test_callback_params_deserialization() {
+        let params: CallbackParams =
+            serde_urlencoded::from_str("code=abc&state=payload.sig").unwrap();
+        assert_eq!(params.code.as_deref(), Some("abc"));
+        assert_eq!(params.state.as_deref(), Some("payload.sig"));
+        assert!(params.error.is_none());
+
+        let params: CallbackParams =
+            serde_urlencoded::from_str("error=access_denied&state=payload.sig").unwrap();
+        assert_eq!(params.error.as_deref(), Some("access_denied"));
+        assert!(params.code.is_none());
+    }
+
+    #[test]
+    fn test_discord_error_status_reports_rate_limits() {
+        let rate_limited = DiscordError::RateLimited {
+            retry_after: std::time::Duration::from_secs(1),
+            global: false,
+        };
+        assert_eq!(
+            discord_error_status(&rate_limited, StatusCode::UNAUTHORIZED),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        let rejected = DiscordError::Status {
+            status: StatusCode::BAD_REQUEST,
+            body: String::new(),
+        };
+        assert_eq!(
+            discord_error_status(&rejected, StatusCode::UNAUTHORIZED),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn test_post_login_redirect_uses_fragment() {
+        let response = post_login_redirect(
+            "https://app.example.com/",
+            &[("access_token", "a.b.c"), ("token_type", "Bearer")],
+        )
+        .into_response();
+
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert_eq!(
+            location,
+            "https://app.example.com/#access_token=a.b.c&token_type=Bearer"
+        );
     }
 
     #[test]
     fn test_token_response_serialization() {
         let response = TokenResponse {
             access_token: "jwt_token_here".to_string(),
+            expires_in: 900,
+            refresh_token: "refresh_token_here".to_string(),
+            csrf_token: None,
             discord_access_token: Some("discord_token_here".to_string()),
         };
 
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("access_token"));
+        assert!(json.contains(r#""refresh_token":"refresh_token_here""#));
+        assert!(json.contains(r#""expires_in":900"#));
         assert!(json.contains("discord_access_token"));
     }
 
@@ -714,6 +978,9 @@ mod tests {
     fn test_token_response_serialization_without_discord_token() {
         let response = TokenResponse {
             access_token: "jwt_token_here".to_string(),
+            expires_in: 900,
+            refresh_token: "refresh_token_here".to_string(),
+            csrf_token: None,
             discord_access_token: None,
         };
 
@@ -722,6 +989,21 @@ mod tests {
         assert!(!json.contains("discord_access_token"));
     }
 
+    #[test]
+    fn test_token_response_serialization_in_cookie_mode() {
+        // Tokens delivered as cookies are blanked and left out of the body
+        let response = TokenResponse {
+            access_token: String::new(),
+            expires_in: 900,
+            refresh_token: String::new(),
+            csrf_token: Some("csrf_token_here".to_string()),
+            discord_access_token: None,
+        };
+
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(json, r#"{"expires_in":900,"csrf_token":"csrf_token_here"}"#);
+    }
+
     #[test]
     fn test_user_response_serialization() {
         let response = UserResponse {
@@ -731,12 +1013,25 @@ mod tests {
             avatar_url: Some("https://example.com/avatar.png".to_string()),
             subscription_tier: SubscriptionTier::Premium,
             is_premium: true,
+            granted_scopes: vec!["identify".to_string(), "email".to_string()],
         };
 
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("123456789"));
         assert!(json.contains("test_user"));
         assert!(json.contains("premium"));
+        assert!(json.contains(r#""granted_scopes":["identify","email"]"#));
+    }
+
+    #[test]
+    fn test_discord_token_response_granted_scopes() {
+        let token: DiscordTokenResponse = serde_json::from_str(
+            r#"{"access_token": "a", "token_type": "Bearer", "expires_in": 604800,
+                "refresh_token": "r", "scope": "identify guilds email"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(token.granted_scopes(), vec!["identify", "guilds", "email"]);
     }
 
     #[test]
@@ -757,4 +1052,148 @@ mod tests {
 
         assert_eq!(avatar_url, "https://cdn.discordapp.com/embed/avatars/0.png");
     }
+
+    #[cfg(feature = "memory-storage")]
+    #[tokio::test]
+    async fn test_exchange_and_revoke_against_mock_discord() {
+        use std::sync::Arc;
+
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        use crate::{
+            storage::MemoryStorage,
+            testing::{MockDiscord, MockEndpoint},
+            AppState,
+        };
+
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(AppState::new(discord.config(), MemoryStorage::new()));
+        let app = super::auth_router().with_state(state.clone());
+
+        let request = axum::http::Request::post("/exchange")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"code": "abc"}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let tokens: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tokens["discord_access_token"], "mock_access_token");
+
+        let exchange = &discord.requests_to(MockEndpoint::Token)[0];
+        assert_eq!(exchange.form_value("code").as_deref(), Some("abc"));
+        assert_eq!(
+            exchange.form_value("grant_type").as_deref(),
+            Some("authorization_code")
+        );
+
+        let request = axum::http::Request::post("/revoke")
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", tokens["access_token"].as_str().unwrap()),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let mut events = state.events.subscribe();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 204);
+
+        // A single forced logout closes every connection
+        assert_eq!(
+            events.try_recv().unwrap().event,
+            crate::events::AccountEvent::ForcedLogout { session_id: None }
+        );
+        assert!(events.try_recv().is_err());
+
+        let revoke = &discord.requests_to(MockEndpoint::Revoke)[0];
+        assert_eq!(
+            revoke.form_value("token").as_deref(),
+            Some("mock_refresh_token")
+        );
+    }
+
+    #[cfg(feature = "memory-storage")]
+    #[tokio::test]
+    async fn test_exchange_rejects_replayed_state() {
+        use std::sync::Arc;
+
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        use crate::{storage::MemoryStorage, testing::MockDiscord, AppState};
+
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(AppState::new(discord.config(), MemoryStorage::new()));
+        let app = super::auth_router().with_state(state);
+
+        let request = axum::http::Request::post("/authorize")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let authorize: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let exchange = serde_json::json!({
+            "code": "abc",
+            "state": authorize["state"],
+            "code_verifier": authorize["code_verifier"],
+        })
+        .to_string();
+
+        for expected in [StatusCode::OK, StatusCode::UNAUTHORIZED] {
+            let request = axum::http::Request::post("/exchange")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(exchange.clone()))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+        }
+    }
+
+    #[cfg(feature = "memory-storage")]
+    #[tokio::test]
+    async fn test_refresh_rejected_after_token_version_bump() {
+        use std::sync::Arc;
+
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        use crate::{storage::MemoryStorage, testing::MockDiscord, AppState};
+
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(AppState::new(discord.config(), MemoryStorage::new()));
+        let app = super::auth_router().with_state(state.clone());
+
+        let request = axum::http::Request::post("/exchange")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"code": "abc"}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let tokens: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let user_id = crate::auth::validate_token(
+            tokens["access_token"].as_str().unwrap(),
+            &state.config.security,
+        )
+        .unwrap()
+        .sub
+        .parse::<i64>()
+        .unwrap();
+
+        state.storage.bump_token_version(user_id).await.unwrap();
+
+        let refresh = serde_json::json!({"refresh_token": tokens["refresh_token"]}).to_string();
+        let request = axum::http::Request::post("/refresh")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(refresh))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,160 @@
+//! Server-Sent Events route.
+//!
+//! `GET /events` streams the authenticated user's account events from the
+//! `EventBus`. `EventSource` cannot set headers, so browsers authenticate with
+//! a single-use `?ticket=` from `POST /ws-ticket` or with cookie sessions.
+//! Each event is sent with its `type` tag as the SSE event name and its JSON
+//! as the data. The stream ends after a `forced_logout` event for the session
+//! it was opened with.
+
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{stream, Stream, StreamExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{
+    auth::EventStreamUser,
+    events::{AccountEvent, UserEvent},
+    AppState,
+};
+
+/// Stream the authenticated user's account events as Server-Sent Events.
+pub async fn event_stream(
+    EventStreamUser(user): EventStreamUser,
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    tracing::debug!("Event stream opened for user {}", user.user_id);
+
+    let events = user_events(state.events.subscribe(), user.user_id, user.session_id).filter_map(
+        |event| async move {
+            match Event::default().event(event.name()).json_data(&event) {
+                Ok(sse) => Some(Ok(sse)),
+                Err(e) => {
+                    tracing::error!("Failed to serialize account event: {}", e);
+                    None
+                }
+            }
+        },
+    );
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Account events for one user, ending after a forced logout of `session_id`.
+fn user_events(
+    receiver: broadcast::Receiver<UserEvent>,
+    user_id: i64,
+    session_id: Option<Uuid>,
+) -> impl Stream<Item = AccountEvent> {
+    stream::unfold(Some(receiver), move |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(UserEvent { user_id: id, event }) if id == user_id => {
+                    let receiver = (!event.ends_session(session_id)).then_some(receiver);
+                    return Some((event, receiver));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        "Event stream for user {} missed {} account events",
+                        user_id,
+                        skipped
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+
+    #[tokio::test]
+    async fn test_user_events_filters_and_ends_on_logout() {
+        let bus = EventBus::new();
+        let session = Some(Uuid::new_v4());
+        let events = user_events(bus.subscribe(), 1, session);
+
+        bus.publish(2, AccountEvent::TokensRevoked);
+        bus.publish(1, AccountEvent::TokensRevoked);
+        bus.publish(
+            1,
+            AccountEvent::ForcedLogout {
+                session_id: Some(Uuid::new_v4()),
+            },
+        );
+        bus.publish(
+            1,
+            AccountEvent::ForcedLogout {
+                session_id: session,
+            },
+        );
+        bus.publish(1, AccountEvent::TokensRevoked);
+
+        let received: Vec<_> = events.collect().await;
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0], AccountEvent::TokensRevoked);
+        assert_eq!(
+            received[2],
+            AccountEvent::ForcedLogout {
+                session_id: session
+            }
+        );
+    }
+
+    #[cfg(feature = "memory-storage")]
+    #[tokio::test]
+    async fn test_event_stream_accepts_single_use_tickets() {
+        use axum::{
+            body::Body,
+            http::{header, Request, StatusCode},
+        };
+        use tower::ServiceExt;
+
+        use crate::{routes::auth_router, storage::MemoryStorage, testing::MockDiscord};
+
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(AppState::new(discord.config(), MemoryStorage::new()));
+        let app = auth_router().with_state(state);
+        let json = |response: axum::response::Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let request = Request::post("/exchange")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"code": "abc"}"#))
+            .unwrap();
+        let tokens = json(app.clone().oneshot(request).await.unwrap()).await;
+        let request = Request::post("/ws-ticket")
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", tokens["access_token"].as_str().unwrap()),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let ticket = json(app.clone().oneshot(request).await.unwrap()).await;
+        let uri = format!("/events?ticket={}", ticket["ticket"].as_str().unwrap());
+
+        for expected in [StatusCode::OK, StatusCode::UNAUTHORIZED] {
+            let request = Request::get(&uri).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+        }
+
+        let request = Request::get("/events").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,206 @@
+//! Discord Interactions Endpoint route.
+//!
+//! Receives the slash commands, components and modal submissions Discord
+//! posts to the application's Interactions Endpoint URL, verifies their
+//! signature against `DiscordConfig::public_key` and dispatches them to the
+//! handlers registered on `AppState::interactions`.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Json, Router,
+};
+
+use super::webhooks::verify_discord_request;
+use crate::{
+    interactions::{Interaction, InteractionRequest, InteractionResponse, INTERACTION_PING},
+    AppState,
+};
+
+/// Create an Axum router with the Discord Interactions Endpoint route.
+///
+/// Set the application's Interactions Endpoint URL in the Developer Portal to
+/// this route. Requires `DiscordConfig::public_key`; without it every request
+/// is rejected, and Discord refuses to save the URL.
+///
+/// Routes:
+/// - `POST /interactions` - Receive a signed Discord interaction
+pub fn interactions_router() -> Router<Arc<AppState>> {
+    Router::new().route("/interactions", post(receive_interaction))
+}
+
+/// Receive an interaction: answer PINGs and dispatch everything else.
+///
+/// The invoking user (`member.user` in guilds, `user` in DMs) is looked up in
+/// storage and passed to the handler. Returns 404 if no handler is registered
+/// for the interaction and 500 if the handler fails.
+pub async fn receive_interaction(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<InteractionResponse>, StatusCode> {
+    verify_discord_request(&state, &headers, &body)?;
+    let interaction: Interaction = serde_json::from_slice(&body).map_err(|e| {
+        tracing::warn!("Malformed Discord interaction: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    if interaction.kind == INTERACTION_PING {
+        return Ok(Json(InteractionResponse::pong()));
+    }
+    let Some(handler) = state.interactions.get(&interaction) else {
+        tracing::warn!(
+            "No handler for Discord interaction {:?} of type {}",
+            interaction.handler_name(),
+            interaction.kind
+        );
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let user = match interaction.user_id() {
+        Some(user_id) => state
+            .storage
+            .get_user(user_id, &state.config.security.encryption_key)
+            .await
+            .map_err(|e| {
+                tracing::error!("Storage error fetching user {}: {}", user_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?,
+        None => None,
+    };
+
+    let name = interaction.handler_name().unwrap_or_default().to_string();
+    handler
+        .handle(&state, InteractionRequest { interaction, user })
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Interaction handler {} failed: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[cfg(all(test, feature = "memory-storage"))]
+mod tests {
+    use async_trait::async_trait;
+    use axum::{body::Body, http::Request};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        interactions::{InteractionHandler, INTERACTION_APPLICATION_COMMAND},
+        storage::MemoryStorage,
+        testing::MockDiscord,
+    };
+
+    /// Replies with the invoker's username, or the premium upsell.
+    struct Whoami;
+
+    #[async_trait]
+    impl InteractionHandler for Whoami {
+        async fn handle(
+            &self,
+            state: &AppState,
+            request: InteractionRequest,
+        ) -> anyhow::Result<InteractionResponse> {
+            if !request.is_premium() {
+                return Ok(InteractionResponse::premium_required(&state.config.discord));
+            }
+            let user = request.user.expect("premium users are stored");
+            Ok(InteractionResponse::ephemeral(user.username))
+        }
+    }
+
+    async fn send(
+        discord: &MockDiscord,
+        state: &Arc<AppState>,
+        payload: &Value,
+    ) -> (StatusCode, Value) {
+        let body = serde_json::to_vec(payload).unwrap();
+        let mut request = Request::post("/interactions")
+            .body(Body::from(body.clone()))
+            .unwrap();
+        request.headers_mut().extend(discord.sign(&body));
+        let response = interactions_router()
+            .with_state(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn command(name: &str, user_id: &str) -> Value {
+        json!({
+            "id": "1",
+            "application_id": "mock_client_id",
+            "type": INTERACTION_APPLICATION_COMMAND,
+            "token": "token",
+            "member": {"user": {"id": user_id, "username": "someone"}},
+            "data": {"id": "2", "name": name, "type": 1},
+        })
+    }
+
+    #[tokio::test]
+    async fn test_interactions_dispatch_to_handlers() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(
+            discord
+                .state_with_user(42)
+                .await
+                .with_interaction_handler("whoami", Whoami),
+        );
+
+        let ping = json!({"id": "1", "application_id": "mock_client_id", "type": 1, "token": "t"});
+        assert_eq!(
+            send(&discord, &state, &ping).await,
+            (StatusCode::OK, json!({"type": 1}))
+        );
+
+        let (status, response) = send(&discord, &state, &command("whoami", "42")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response["data"]["components"][0]["components"][0]["sku_id"],
+            "777"
+        );
+
+        state
+            .storage
+            .update_subscription(
+                42,
+                crate::SubscriptionTier::Premium,
+                crate::SubscriptionSource::Manual,
+                None,
+            )
+            .await
+            .unwrap();
+        let (_, response) = send(&discord, &state, &command("whoami", "42")).await;
+        assert_eq!(response["data"]["content"], "alice");
+
+        let (status, _) = send(&discord, &state, &command("unknown", "42")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_interactions_reject_unsigned_requests() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(AppState::new(discord.config(), MemoryStorage::new()));
+        let request = Request::post("/interactions")
+            .body(Body::from(command("whoami", "42").to_string()))
+            .unwrap();
+        let response = interactions_router()
+            .with_state(state)
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
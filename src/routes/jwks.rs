@@ -0,0 +1,39 @@
+//! JWKS publication route.
+//!
+//! This module provides the `/.well-known/jwks.json` handler that publishes the
+//! public keys used to sign catacombs JWTs.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+
+use crate::{jwks, AppState};
+
+/// Create an Axum router with the JWKS route.
+///
+/// Mount this at the root of the application (not under the auth prefix) so
+/// the well-known path resolves as expected.
+///
+/// Routes:
+/// - `GET /.well-known/jwks.json` - Public keys for verifying issued JWTs
+pub fn jwks_router() -> Router<Arc<AppState>> {
+    Router::new().route("/.well-known/jwks.json", get(get_jwks))
+}
+
+/// Publish the current and still-accepted asymmetric JWT public keys.
+///
+/// Returns an empty key set when tokens are signed with `HS256`.
+pub async fn get_jwks(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse, StatusCode> {
+    let set = jwks::jwk_set(&state.config.security).map_err(|e| {
+        tracing::error!("Failed to build JWKS: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(([(header::CACHE_CONTROL, "public, max-age=300")], Json(set)))
+}
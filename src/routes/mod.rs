@@ -1,5 +1,21 @@
 //! HTTP route handlers for Discord OAuth.
 
+pub mod admin;
 pub mod auth;
+pub mod events;
+pub mod interactions;
+pub mod jwks;
+pub mod sessions;
+pub mod webhooks;
+pub mod ws;
 
-pub use auth::{auth_router, exchange_code, get_current_user, logout, refresh_token, revoke_token};
+pub use admin::admin_router;
+pub use auth::{
+    auth_router, authorize, exchange_code, get_current_user, logout, refresh_token, revoke_token,
+};
+pub use events::event_stream;
+pub use interactions::{interactions_router, receive_interaction};
+pub use jwks::{get_jwks, jwks_router};
+pub use sessions::{delete_session, list_sessions, logout_all};
+pub use webhooks::{events_router, receive_webhook_event};
+pub use ws::{issue_ws_ticket, ws_handler};
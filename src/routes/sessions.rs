@@ -0,0 +1,184 @@
+//! Login session management routes.
+//!
+//! These handlers are mounted by `auth_router()` and let users list the
+//! devices they are logged in on and end any or all of those sessions.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{auth::AuthenticatedUser, cookies, events::AccountEvent, models::Session, AppState};
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// True for the session the request was authenticated with.
+    pub current: bool,
+}
+
+impl SessionResponse {
+    fn new(session: Session, current_session: Option<Uuid>) -> Self {
+        Self {
+            current: current_session == Some(session.session_id),
+            id: session.session_id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// List the authenticated user's active sessions.
+pub async fn list_sessions(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SessionResponse>>, StatusCode> {
+    let sessions = state
+        .storage
+        .list_user_sessions(user.user_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Storage error listing sessions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionResponse::new(session, user.session_id))
+            .collect(),
+    ))
+}
+
+/// End one of the authenticated user's sessions.
+///
+/// Returns 404 if the session does not exist or belongs to another user.
+pub async fn delete_session(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let session = state
+        .storage
+        .get_session(session_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Storage error fetching session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|session| session.user_id == user.user_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    end_session(&state, user.user_id, session.session_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to revoke session {}: {}", session_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tracing::info!("User {} ended session {}", user.user_id, session_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Log the authenticated user out of every session.
+///
+/// Revokes all sessions, refresh tokens and outstanding JWTs and clears the
+/// stored Discord tokens.
+pub async fn logout_all(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<(CookieJar, StatusCode), StatusCode> {
+    tracing::info!(
+        "Logging out user everywhere: {} ({})",
+        user.username,
+        user.user_id
+    );
+
+    end_all_sessions(&state, user.user_id).await.map_err(|e| {
+        tracing::error!("Failed to log out everywhere: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let jar = cookies::clear_session_cookies(jar, &state.config.security);
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+/// Revoke a session and the refresh token family it owns, and publish a
+/// forced logout so its WebSocket and SSE connections close.
+pub(crate) async fn end_session(
+    state: &AppState,
+    user_id: i64,
+    session_id: Uuid,
+) -> crate::Result<()> {
+    state.storage.revoke_session(session_id).await?;
+    state
+        .storage
+        .revoke_refresh_token_family(session_id)
+        .await?;
+    state.events.publish(
+        user_id,
+        AccountEvent::ForcedLogout {
+            session_id: Some(session_id),
+        },
+    );
+    Ok(())
+}
+
+/// Revoke every session, refresh token and JWT of a user and clear the stored
+/// Discord tokens.
+///
+/// Bumping the token version publishes the forced logout that closes all of
+/// the user's connections.
+pub(crate) async fn end_all_sessions(state: &AppState, user_id: i64) -> crate::Result<()> {
+    // Bumping the token version also revokes every session and refresh token
+    state.storage.bump_token_version(user_id).await?;
+    state.storage.clear_user_tokens(user_id).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_session_response_marks_current() {
+        let session_id = Uuid::new_v4();
+        let session = Session {
+            session_id,
+            user_id: 123,
+            user_agent: Some("Mozilla/5.0".to_string()),
+            ip_address: None,
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            expires_at: Utc::now() + Duration::days(30),
+            revoked_at: None,
+        };
+
+        let current = SessionResponse::new(session.clone(), Some(session_id));
+        assert!(current.current);
+        let other = SessionResponse::new(session, Some(Uuid::new_v4()));
+        assert!(!other.current);
+
+        let json = serde_json::to_string(&other).unwrap();
+        assert!(json.contains(&format!(r#""id":"{session_id}""#)));
+        assert!(json.contains(r#""current":false"#));
+    }
+}
@@ -0,0 +1,359 @@
+//! Discord Webhook Events route.
+//!
+//! Discord posts monetization events to the application's Webhook Events URL.
+//! Each request is signed with the application key and verified against
+//! `DiscordConfig::public_key`. Entitlement events are stored and the user's
+//! subscription reconciled at once, so a purchase or refund takes effect
+//! mid-session; the resulting `subscription_changed` account event reaches
+//! the user's open WebSocket and SSE connections.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    discord::{verify_signature, DiscordEntitlement},
+    entitlements, AppState,
+};
+
+/// Webhook payload type of the PING Discord sends to validate the URL.
+pub const WEBHOOK_PING: u8 = 0;
+/// Webhook payload type of an event.
+pub const WEBHOOK_EVENT: u8 = 1;
+
+/// Create an Axum router with the Discord Webhook Events route.
+///
+/// Set the application's Webhook Events URL in the Developer Portal to this
+/// route and subscribe to the entitlement events. Requires
+/// `DiscordConfig::public_key`; without it every request is rejected.
+///
+/// Routes:
+/// - `POST /webhook-events` - Receive a signed Discord webhook event
+pub fn events_router() -> Router<Arc<AppState>> {
+    Router::new().route("/webhook-events", post(receive_webhook_event))
+}
+
+/// A request Discord sends to the Webhook Events URL.
+#[derive(Debug, Deserialize)]
+pub struct WebhookPayload {
+    /// Payload version, currently always 1.
+    pub version: u8,
+    /// Snowflake ID of the application the event is for.
+    pub application_id: String,
+    /// `WEBHOOK_PING` or `WEBHOOK_EVENT`.
+    #[serde(rename = "type")]
+    pub kind: u8,
+    /// The event, absent for PINGs.
+    #[serde(default)]
+    pub event: Option<WebhookEvent>,
+}
+
+/// The event carried by a `WEBHOOK_EVENT` payload.
+#[derive(Debug, Deserialize)]
+pub struct WebhookEvent {
+    /// Event type, e.g. `ENTITLEMENT_CREATE`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// When the event happened.
+    pub timestamp: DateTime<Utc>,
+    /// Event data, an entitlement object for entitlement events.
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+/// Check the signature of a webhook event or interaction Discord sent.
+///
+/// Returns 401, as Discord expects, if the signature does not verify or no
+/// public key is configured.
+pub(crate) fn verify_discord_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), StatusCode> {
+    let Some(public_key) = state.config.discord.public_key.as_deref() else {
+        tracing::warn!("Rejected signed Discord request: DISCORD_PUBLIC_KEY is not set");
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    verify_signature(public_key, headers, body).map_err(|e| {
+        tracing::warn!("Rejected Discord request: {}", e);
+        StatusCode::UNAUTHORIZED
+    })
+}
+
+/// Receive a webhook event: answer PINGs and apply entitlement events.
+///
+/// Answers 204 once the event is handled; unknown event types, and events
+/// superseded by what is already stored, are ignored.
+/// Storage failures answer 500 so that Discord retries the event.
+pub async fn receive_webhook_event(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    if let Err(status) = verify_discord_request(&state, &headers, &body) {
+        return status;
+    }
+    let payload: WebhookPayload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("Malformed Discord webhook payload: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    match (payload.kind, payload.event) {
+        (WEBHOOK_PING, _) => StatusCode::NO_CONTENT,
+        (WEBHOOK_EVENT, Some(event)) => handle_event(&state, event).await,
+        (kind, _) => {
+            tracing::warn!("Unexpected Discord webhook payload type {}", kind);
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+async fn handle_event(state: &AppState, event: WebhookEvent) -> StatusCode {
+    let deleted = match event.kind.as_str() {
+        "ENTITLEMENT_CREATE" | "ENTITLEMENT_UPDATE" => false,
+        "ENTITLEMENT_DELETE" => true,
+        kind => {
+            tracing::debug!("Ignoring Discord webhook event {}", kind);
+            return StatusCode::NO_CONTENT;
+        }
+    };
+
+    let entitlement = event
+        .data
+        .map(serde_json::from_value::<DiscordEntitlement>)
+        .transpose();
+    let mut entitlement = match entitlement {
+        Ok(Some(entitlement)) => entitlement,
+        Ok(None) => {
+            tracing::warn!("Discord webhook event {} has no data", event.kind);
+            return StatusCode::BAD_REQUEST;
+        }
+        Err(e) => {
+            tracing::warn!("Malformed entitlement in {} event: {}", event.kind, e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    entitlement.deleted |= deleted;
+
+    // Discord retries failed deliveries, so events can arrive out of order
+    match is_stale(state, &entitlement, event.timestamp).await {
+        Ok(false) => {}
+        Ok(true) => {
+            tracing::debug!(
+                "Ignoring stale {} event for entitlement {}",
+                event.kind,
+                entitlement.id
+            );
+            return StatusCode::NO_CONTENT;
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to look up entitlement {} for {}: {}",
+                entitlement.id,
+                event.kind,
+                e
+            );
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    tracing::debug!(
+        "Discord webhook event {} for entitlement {}",
+        event.kind,
+        entitlement.id
+    );
+    match entitlements::apply_entitlement(state, &entitlement).await {
+        Ok(_) => StatusCode::NO_CONTENT,
+        Err(e) => {
+            tracing::error!(
+                "Failed to apply {} for entitlement {}: {}",
+                event.kind,
+                entitlement.id,
+                e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Whether an event is superseded by what is stored: it happened before the
+/// entitlement was last stored, or it upserts an entitlement already deleted.
+async fn is_stale(
+    state: &AppState,
+    entitlement: &DiscordEntitlement,
+    timestamp: DateTime<Utc>,
+) -> crate::error::Result<bool> {
+    // Unparseable IDs are rejected by `apply_entitlement`
+    let Ok(entitlement_id) = entitlement.id.parse::<i64>() else {
+        return Ok(false);
+    };
+    if !entitlement.deleted
+        && state
+            .storage
+            .get_entitlement_deleted_at(entitlement_id)
+            .await?
+            .is_some()
+    {
+        return Ok(true);
+    }
+    Ok(state
+        .storage
+        .get_entitlement(entitlement_id)
+        .await?
+        .is_some_and(|stored| timestamp < stored.updated_at))
+}
+
+#[cfg(all(test, feature = "memory-storage"))]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        models::{SubscriptionSource, SubscriptionTier},
+        testing::{mock_entitlement, MockDiscord},
+    };
+
+    async fn send(discord: &MockDiscord, state: &Arc<AppState>, payload: &Value) -> StatusCode {
+        let body = serde_json::to_vec(payload).unwrap();
+        let mut request = Request::post("/webhook-events")
+            .body(Body::from(body.clone()))
+            .unwrap();
+        request.headers_mut().extend(discord.sign(&body));
+        events_router()
+            .with_state(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn subscription(state: &AppState) -> (SubscriptionTier, Option<SubscriptionSource>) {
+        let key = &state.config.security.encryption_key;
+        let user = state.storage.get_user(42, key).await.unwrap().unwrap();
+        (user.subscription_tier, user.subscription_source)
+    }
+
+    fn event(kind: &str, data: Value) -> Value {
+        event_at(kind, data, Utc::now())
+    }
+
+    fn event_at(kind: &str, data: Value, timestamp: DateTime<Utc>) -> Value {
+        json!({
+            "version": 1,
+            "application_id": "mock_client_id",
+            "type": WEBHOOK_EVENT,
+            "event": {"type": kind, "timestamp": timestamp, "data": data},
+        })
+    }
+
+    #[tokio::test]
+    async fn test_webhook_rejects_bad_signatures() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(discord.state_with_user(42).await);
+        let ping = json!({"version": 1, "application_id": "mock_client_id", "type": WEBHOOK_PING});
+        assert_eq!(send(&discord, &state, &ping).await, StatusCode::NO_CONTENT);
+
+        let mut request = Request::post("/webhook-events")
+            .body(Body::from(ping.to_string()))
+            .unwrap();
+        request.headers_mut().extend(discord.sign(br#"{"type":0}"#));
+        let response = events_router()
+            .with_state(state)
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_entitlement_events_update_tier() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(discord.state_with_user(42).await);
+
+        let create = event("ENTITLEMENT_CREATE", mock_entitlement(1, 42, 777));
+        assert_eq!(
+            send(&discord, &state, &create).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            subscription(&state).await,
+            (SubscriptionTier::Premium, Some(SubscriptionSource::Discord))
+        );
+
+        let delete = event("ENTITLEMENT_DELETE", mock_entitlement(1, 42, 777));
+        assert_eq!(
+            send(&discord, &state, &delete).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(subscription(&state).await.0, SubscriptionTier::Free);
+        assert!(state
+            .storage
+            .get_user_entitlements(42)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Users that never logged in are left for their first sync
+        let unknown = event("ENTITLEMENT_CREATE", mock_entitlement(2, 43, 777));
+        assert_eq!(
+            send(&discord, &state, &unknown).await,
+            StatusCode::NO_CONTENT
+        );
+        assert!(state
+            .storage
+            .get_user_entitlements(43)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_ignores_late_entitlement_events() {
+        let discord = MockDiscord::start().await.unwrap();
+        let state = Arc::new(discord.state_with_user(42).await);
+        let created = Utc::now() - chrono::Duration::minutes(10);
+
+        // A retried UPDATE delivered after the DELETE that followed it
+        let create = event_at("ENTITLEMENT_CREATE", mock_entitlement(1, 42, 777), created);
+        assert_eq!(
+            send(&discord, &state, &create).await,
+            StatusCode::NO_CONTENT
+        );
+        let delete = event("ENTITLEMENT_DELETE", mock_entitlement(1, 42, 777));
+        assert_eq!(
+            send(&discord, &state, &delete).await,
+            StatusCode::NO_CONTENT
+        );
+        let late = event_at("ENTITLEMENT_UPDATE", mock_entitlement(1, 42, 777), created);
+        assert_eq!(send(&discord, &state, &late).await, StatusCode::NO_CONTENT);
+        assert_eq!(subscription(&state).await.0, SubscriptionTier::Free);
+        assert!(state.storage.get_entitlement(1).await.unwrap().is_none());
+
+        // An UPDATE older than what is stored does not overwrite it
+        let create = event("ENTITLEMENT_CREATE", mock_entitlement(2, 42, 777));
+        assert_eq!(
+            send(&discord, &state, &create).await,
+            StatusCode::NO_CONTENT
+        );
+        let mut ended = mock_entitlement(2, 42, 777);
+        ended["ends_at"] = json!(created);
+        let late = event_at("ENTITLEMENT_UPDATE", ended, created);
+        assert_eq!(send(&discord, &state, &late).await, StatusCode::NO_CONTENT);
+        assert_eq!(subscription(&state).await.0, SubscriptionTier::Premium);
+        let stored = state.storage.get_entitlement(2).await.unwrap().unwrap();
+        assert_eq!(stored.ends_at, None);
+    }
+}
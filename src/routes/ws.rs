@@ -0,0 +1,90 @@
+//! WebSocket routes.
+//!
+//! Browsers cannot set headers on WebSocket upgrades, so clients first trade
+//! their JWT for a short-lived ticket at `/ws-ticket` and pass it as
+//! `?ticket=` when connecting to `/ws`. Upgrade handlers accept it with the
+//! `WsTicketUser` extractor. The same tickets authenticate `/events` streams.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{State, WebSocketUpgrade},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::{
+    auth::{self, AuthenticatedUser, WsTicketUser},
+    models::WsTicketParams,
+    AppState,
+};
+
+/// How long a WebSocket ticket stays valid.
+pub const WS_TICKET_TTL_SECONDS: i64 = 30;
+
+#[derive(Debug, Serialize)]
+pub struct WsTicketResponse {
+    /// Single-use ticket to pass as `?ticket=` on the upgrade or `/events` request.
+    pub ticket: String,
+    /// Lifetime of `ticket`, in seconds.
+    pub expires_in: i64,
+}
+
+/// Issue a single-use ticket that authenticates one WebSocket upgrade or event stream.
+pub async fn issue_ws_ticket(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<WsTicketResponse>, StatusCode> {
+    // Tickets share the refresh token format: random, and only stored hashed
+    let ticket = auth::generate_refresh_token();
+    state
+        .storage
+        .create_ws_ticket(WsTicketParams {
+            ticket_hash: &auth::hash_refresh_token(&ticket),
+            user_id: user.user_id,
+            username: &user.username,
+            scopes: &user.scopes,
+            roles: &user.roles,
+            session_id: user.session_id,
+            expires_at: Utc::now() + chrono::Duration::seconds(WS_TICKET_TTL_SECONDS),
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to store WebSocket ticket: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tracing::debug!("Issued WebSocket ticket for user {}", user.user_id);
+    Ok(Json(WsTicketResponse {
+        ticket,
+        expires_in: WS_TICKET_TTL_SECONDS,
+    }))
+}
+
+/// Open a WebSocket on the `WsHub`, authenticated by a ticket from `/ws-ticket`.
+pub async fn ws_handler(
+    WsTicketUser(user): WsTicketUser,
+    State(state): State<Arc<AppState>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| async move { state.ws_hub.serve(socket, user).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ws_ticket_response_serialization() {
+        let response = WsTicketResponse {
+            ticket: "ticket_here".to_string(),
+            expires_in: WS_TICKET_TTL_SECONDS,
+        };
+
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(json, r#"{"ticket":"ticket_here","expires_in":30}"#);
+    }
+}
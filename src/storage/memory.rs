@@ -1,39 +1,66 @@
 //! In-memory storage implementation for testing.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
+use uuid::Uuid;
 
 use crate::{
     error::Result,
     models::{
-        EntitlementUpsertParams, SubscriptionSource, SubscriptionTier, User, UserUpsertParams,
+        Entitlement, EntitlementUpsertParams, RefreshTokenParams, RefreshTokenStatus, Session,
+        SessionCreateParams, SubscriptionDecision, SubscriptionSource, SubscriptionTier, User,
+        UserListParams, UserUpsertParams, WsTicket, WsTicketParams, ADMIN_ROLE,
+        WILDCARD_PERMISSION,
     },
-    storage::{EntitlementStorage, UserStorage},
+    storage::{storage_error, EntitlementStorage, RoleStorage, SessionStorage, UserStorage},
 };
 
 /// In-memory storage backend for testing and development.
 ///
 /// Note: This implementation stores refresh tokens in plaintext (no encryption)
-/// since it's intended for testing only.
-#[derive(Debug, Default)]
+/// since it's intended for testing only. Like the migrations, it starts with
+/// the `admin` role defined.
+#[derive(Debug)]
 pub struct MemoryStorage {
     users: RwLock<HashMap<i64, User>>,
-    entitlements: RwLock<HashMap<i64, StoredEntitlement>>,
+    entitlements: RwLock<HashMap<i64, Entitlement>>,
+    deleted_entitlements: RwLock<HashMap<i64, DateTime<Utc>>>,
+    subscription_decisions: RwLock<Vec<SubscriptionDecision>>,
+    refresh_tokens: RwLock<HashMap<String, StoredRefreshToken>>,
+    sessions: RwLock<HashMap<Uuid, Session>>,
+    ws_tickets: RwLock<HashMap<String, WsTicket>>,
+    oauth_states: RwLock<HashMap<String, DateTime<Utc>>>,
+    roles: RwLock<HashMap<String, BTreeSet<String>>>,
+    user_roles: RwLock<HashMap<i64, BTreeSet<String>>>,
 }
 
 #[derive(Debug, Clone)]
-struct StoredEntitlement {
-    entitlement_id: i64,
+struct StoredRefreshToken {
     user_id: i64,
-    sku_id: i64,
-    entitlement_type: i32,
-    is_test: bool,
-    consumed: bool,
-    starts_at: Option<DateTime<Utc>>,
-    ends_at: Option<DateTime<Utc>>,
+    family_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used: bool,
+    revoked: bool,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self {
+            users: RwLock::default(),
+            entitlements: RwLock::default(),
+            deleted_entitlements: RwLock::default(),
+            subscription_decisions: RwLock::default(),
+            refresh_tokens: RwLock::default(),
+            sessions: RwLock::default(),
+            ws_tickets: RwLock::default(),
+            oauth_states: RwLock::default(),
+            roles: RwLock::new(default_roles()),
+            user_roles: RwLock::default(),
+        }
+    }
 }
 
 impl MemoryStorage {
@@ -43,9 +70,19 @@ impl MemoryStorage {
     }
 
     /// Clear all stored data (useful for test cleanup).
+    ///
+    /// Roles are reset to the built-in `admin` role.
     pub fn clear(&self) {
         self.users.write().clear();
         self.entitlements.write().clear();
+        self.deleted_entitlements.write().clear();
+        self.subscription_decisions.write().clear();
+        self.refresh_tokens.write().clear();
+        self.sessions.write().clear();
+        self.ws_tickets.write().clear();
+        self.oauth_states.write().clear();
+        *self.roles.write() = default_roles();
+        self.user_roles.write().clear();
     }
 
     /// Get the number of stored users.
@@ -65,6 +102,30 @@ impl UserStorage for MemoryStorage {
         Ok(self.users.read().get(&user_id).cloned())
     }
 
+    async fn list_users(&self, params: UserListParams<'_>) -> Result<Vec<User>> {
+        let mut users: Vec<User> = self
+            .users
+            .read()
+            .values()
+            .filter(|user| params.matches(user))
+            .cloned()
+            .map(|user| User {
+                refresh_token: None,
+                ..user
+            })
+            .collect();
+        users.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then(b.user_id.cmp(&a.user_id))
+        });
+        Ok(users
+            .into_iter()
+            .skip(usize::try_from(params.offset).unwrap_or_default())
+            .take(usize::try_from(params.limit).unwrap_or_default())
+            .collect())
+    }
+
     async fn upsert_user(&self, params: UserUpsertParams<'_>, _encryption_key: &str) -> Result<()> {
         let mut users = self.users.write();
         let now = Utc::now();
@@ -79,6 +140,9 @@ impl UserStorage for MemoryStorage {
             if params.token_expires_at.is_some() {
                 existing.token_expires_at = params.token_expires_at;
             }
+            if let Some(scopes) = params.granted_scopes {
+                existing.granted_scopes = scopes.to_vec();
+            }
             existing.updated_at = now;
         } else {
             users.insert(
@@ -90,6 +154,11 @@ impl UserStorage for MemoryStorage {
                     avatar_url: params.avatar_url.map(String::from),
                     refresh_token: params.refresh_token.map(String::from),
                     token_expires_at: params.token_expires_at,
+                    granted_scopes: params
+                        .granted_scopes
+                        .map(<[String]>::to_vec)
+                        .unwrap_or_default(),
+                    token_version: 0,
                     subscription_tier: SubscriptionTier::Free,
                     subscription_source: None,
                     subscription_expires_at: None,
@@ -144,15 +213,94 @@ impl UserStorage for MemoryStorage {
         }
         Ok(())
     }
+
+    async fn store_refresh_token(&self, params: RefreshTokenParams<'_>) -> Result<()> {
+        self.refresh_tokens.write().insert(
+            params.token_hash.to_string(),
+            StoredRefreshToken {
+                user_id: params.user_id,
+                family_id: params.family_id,
+                expires_at: params.expires_at,
+                used: false,
+                revoked: false,
+            },
+        );
+        Ok(())
+    }
+
+    async fn consume_refresh_token(&self, token_hash: &str) -> Result<RefreshTokenStatus> {
+        let mut tokens = self.refresh_tokens.write();
+        let Some(token) = tokens.get_mut(token_hash) else {
+            return Ok(RefreshTokenStatus::Invalid);
+        };
+
+        if token.used {
+            return Ok(RefreshTokenStatus::Reused {
+                user_id: token.user_id,
+                family_id: token.family_id,
+            });
+        }
+        if token.revoked || token.expires_at <= Utc::now() {
+            return Ok(RefreshTokenStatus::Invalid);
+        }
+
+        token.used = true;
+        Ok(RefreshTokenStatus::Valid {
+            user_id: token.user_id,
+            family_id: token.family_id,
+        })
+    }
+
+    async fn revoke_refresh_token_family(&self, family_id: Uuid) -> Result<()> {
+        for token in self.refresh_tokens.write().values_mut() {
+            if token.family_id == family_id {
+                token.revoked = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_refresh_tokens(&self, user_id: i64) -> Result<()> {
+        for token in self.refresh_tokens.write().values_mut() {
+            if token.user_id == user_id {
+                token.revoked = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_token_version(&self, user_id: i64) -> Result<Option<i32>> {
+        Ok(self
+            .users
+            .read()
+            .get(&user_id)
+            .map(|user| user.token_version))
+    }
+
+    async fn bump_token_version(&self, user_id: i64) -> Result<()> {
+        if let Some(user) = self.users.write().get_mut(&user_id) {
+            user.token_version += 1;
+            user.updated_at = Utc::now();
+        }
+        self.ws_tickets
+            .write()
+            .retain(|_, ticket| ticket.user_id != user_id);
+        self.revoke_user_refresh_tokens(user_id).await?;
+        self.revoke_user_sessions(user_id).await
+    }
 }
 
 #[async_trait]
 impl EntitlementStorage for MemoryStorage {
     async fn upsert_entitlement(&self, params: EntitlementUpsertParams) -> Result<()> {
         let mut entitlements = self.entitlements.write();
+        let now = Utc::now();
+        let created_at = entitlements
+            .get(&params.entitlement_id)
+            .map_or(now, |existing| existing.created_at);
         entitlements.insert(
             params.entitlement_id,
-            StoredEntitlement {
+            Entitlement {
                 entitlement_id: params.entitlement_id,
                 user_id: params.user_id,
                 sku_id: params.sku_id,
@@ -161,10 +309,243 @@ impl EntitlementStorage for MemoryStorage {
                 consumed: params.consumed,
                 starts_at: params.starts_at,
                 ends_at: params.ends_at,
+                created_at,
+                updated_at: now,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_user_entitlements(&self, user_id: i64) -> Result<Vec<Entitlement>> {
+        let mut entitlements: Vec<Entitlement> = self
+            .entitlements
+            .read()
+            .values()
+            .filter(|entitlement| entitlement.user_id == user_id)
+            .cloned()
+            .collect();
+        entitlements.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then(b.entitlement_id.cmp(&a.entitlement_id))
+        });
+        Ok(entitlements)
+    }
+
+    async fn get_entitlement(&self, entitlement_id: i64) -> Result<Option<Entitlement>> {
+        Ok(self.entitlements.read().get(&entitlement_id).cloned())
+    }
+
+    async fn delete_entitlement(&self, entitlement_id: i64) -> Result<()> {
+        self.entitlements.write().remove(&entitlement_id);
+        self.deleted_entitlements
+            .write()
+            .entry(entitlement_id)
+            .or_insert_with(Utc::now);
+        Ok(())
+    }
+
+    async fn get_entitlement_deleted_at(
+        &self,
+        entitlement_id: i64,
+    ) -> Result<Option<DateTime<Utc>>> {
+        Ok(self
+            .deleted_entitlements
+            .read()
+            .get(&entitlement_id)
+            .copied())
+    }
+
+    async fn record_subscription_decision(&self, decision: &SubscriptionDecision) -> Result<()> {
+        self.subscription_decisions.write().push(decision.clone());
+        Ok(())
+    }
+
+    async fn get_subscription_decisions(
+        &self,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SubscriptionDecision>> {
+        Ok(self
+            .subscription_decisions
+            .read()
+            .iter()
+            .rev()
+            .filter(|decision| decision.user_id == user_id)
+            .take(usize::try_from(limit).unwrap_or_default())
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl SessionStorage for MemoryStorage {
+    async fn create_session(&self, params: SessionCreateParams<'_>) -> Result<()> {
+        let now = Utc::now();
+        self.sessions.write().insert(
+            params.session_id,
+            Session {
+                session_id: params.session_id,
+                user_id: params.user_id,
+                user_agent: params.user_agent.map(String::from),
+                ip_address: params.ip_address.map(String::from),
+                created_at: now,
+                last_seen_at: now,
+                expires_at: params.expires_at,
+                revoked_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>> {
+        Ok(self.sessions.read().get(&session_id).cloned())
+    }
+
+    async fn list_user_sessions(&self, user_id: i64) -> Result<Vec<Session>> {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .read()
+            .values()
+            .filter(|s| s.user_id == user_id && s.is_active())
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen_at));
+        Ok(sessions)
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        if let Some(session) = self.sessions.write().get_mut(&session_id) {
+            session.last_seen_at = Utc::now();
+            if let Some(expires_at) = expires_at {
+                session.expires_at = expires_at;
+            }
+        }
+        Ok(())
+    }
+
+    async fn revoke_session(&self, session_id: Uuid) -> Result<()> {
+        if let Some(session) = self.sessions.write().get_mut(&session_id) {
+            session.revoked_at.get_or_insert_with(Utc::now);
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: i64) -> Result<()> {
+        let now = Utc::now();
+        for session in self.sessions.write().values_mut() {
+            if session.user_id == user_id {
+                session.revoked_at.get_or_insert(now);
+            }
+        }
+        Ok(())
+    }
+
+    async fn create_ws_ticket(&self, params: WsTicketParams<'_>) -> Result<()> {
+        let now = Utc::now();
+        let mut tickets = self.ws_tickets.write();
+        tickets.retain(|_, ticket| ticket.expires_at > now);
+        tickets.insert(
+            params.ticket_hash.to_string(),
+            WsTicket {
+                user_id: params.user_id,
+                username: params.username.to_string(),
+                scopes: params.scopes.to_vec(),
+                roles: params.roles.to_vec(),
+                session_id: params.session_id,
+                expires_at: params.expires_at,
             },
         );
         Ok(())
     }
+
+    async fn consume_ws_ticket(&self, ticket_hash: &str) -> Result<Option<WsTicket>> {
+        Ok(self
+            .ws_tickets
+            .write()
+            .remove(ticket_hash)
+            .filter(|ticket| ticket.expires_at > Utc::now()))
+    }
+
+    async fn consume_oauth_state(&self, nonce: &str, expires_at: DateTime<Utc>) -> Result<bool> {
+        let now = Utc::now();
+        let mut states = self.oauth_states.write();
+        states.retain(|_, expires_at| *expires_at > now);
+        if states.contains_key(nonce) {
+            return Ok(false);
+        }
+        states.insert(nonce.to_string(), expires_at);
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl RoleStorage for MemoryStorage {
+    async fn upsert_role(&self, name: &str, permissions: &[String]) -> Result<()> {
+        self.roles
+            .write()
+            .insert(name.to_string(), permissions.iter().cloned().collect());
+        Ok(())
+    }
+
+    async fn grant_role(&self, user_id: i64, role: &str) -> Result<()> {
+        if !self.roles.read().contains_key(role) {
+            return Err(storage_error(format!("unknown role: {role}")).into());
+        }
+        if !self.users.read().contains_key(&user_id) {
+            return Err(storage_error(format!("unknown user: {user_id}")).into());
+        }
+
+        self.user_roles
+            .write()
+            .entry(user_id)
+            .or_default()
+            .insert(role.to_string());
+        Ok(())
+    }
+
+    async fn revoke_role(&self, user_id: i64, role: &str) -> Result<()> {
+        if let Some(roles) = self.user_roles.write().get_mut(&user_id) {
+            roles.remove(role);
+        }
+        Ok(())
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>> {
+        Ok(self
+            .user_roles
+            .read()
+            .get(&user_id)
+            .map(|roles| roles.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn get_user_permissions(&self, user_id: i64) -> Result<Vec<String>> {
+        let roles = self.roles.read();
+        let permissions: BTreeSet<String> = self
+            .user_roles
+            .read()
+            .get(&user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|role| roles.get(role))
+            .flatten()
+            .cloned()
+            .collect();
+        Ok(permissions.into_iter().collect())
+    }
+}
+
+/// Roles the migrations create.
+fn default_roles() -> HashMap<String, BTreeSet<String>> {
+    HashMap::from([(
+        ADMIN_ROLE.to_string(),
+        BTreeSet::from([WILDCARD_PERMISSION.to_string()]),
+    )])
 }
 
 #[cfg(test)]
@@ -172,6 +553,7 @@ mod tests {
     use chrono::Duration;
 
     use super::*;
+    use crate::models::SubscriptionAction;
 
     #[tokio::test]
     async fn test_memory_storage_user_lifecycle() {
@@ -191,6 +573,7 @@ mod tests {
                     avatar_url: None,
                     refresh_token: Some("token123"),
                     token_expires_at: Some(Utc::now() + Duration::hours(1)),
+                    granted_scopes: Some(&["identify".to_string(), "email".to_string()]),
                 },
                 key,
             )
@@ -201,6 +584,7 @@ mod tests {
         let user = storage.get_user(123, key).await.unwrap().unwrap();
         assert_eq!(user.username, "testuser");
         assert_eq!(user.refresh_token, Some("token123".to_string()));
+        assert!(user.has_scope("email"));
 
         // Update user
         storage
@@ -212,6 +596,7 @@ mod tests {
                     avatar_url: None,
                     refresh_token: None,
                     token_expires_at: None,
+                    granted_scopes: None,
                 },
                 key,
             )
@@ -220,8 +605,9 @@ mod tests {
 
         let user = storage.get_user(123, key).await.unwrap().unwrap();
         assert_eq!(user.username, "newname");
-        // Token preserved when not provided
+        // Token and scopes preserved when not provided
         assert_eq!(user.refresh_token, Some("token123".to_string()));
+        assert!(user.has_scope("email"));
 
         // Clear tokens
         storage.clear_user_tokens(123).await.unwrap();
@@ -243,6 +629,7 @@ mod tests {
                     avatar_url: None,
                     refresh_token: None,
                     token_expires_at: None,
+                    granted_scopes: None,
                 },
                 key,
             )
@@ -269,6 +656,91 @@ mod tests {
         assert!(user.is_premium());
     }
 
+    #[tokio::test]
+    async fn test_memory_storage_token_version() {
+        let storage = MemoryStorage::new();
+        let key = "unused";
+
+        assert!(storage.get_token_version(789).await.unwrap().is_none());
+
+        storage
+            .upsert_user(
+                UserUpsertParams {
+                    user_id: 789,
+                    username: "versioned",
+                    global_name: None,
+                    avatar_url: None,
+                    refresh_token: None,
+                    token_expires_at: None,
+                    granted_scopes: None,
+                },
+                key,
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.get_token_version(789).await.unwrap(), Some(0));
+
+        let session_id = Uuid::new_v4();
+        let expires_at = Utc::now() + Duration::days(30);
+        storage
+            .create_session(SessionCreateParams {
+                session_id,
+                user_id: 789,
+                user_agent: None,
+                ip_address: None,
+                expires_at,
+            })
+            .await
+            .unwrap();
+        storage
+            .store_refresh_token(RefreshTokenParams {
+                token_hash: "versioned_hash",
+                user_id: 789,
+                family_id: session_id,
+                expires_at,
+            })
+            .await
+            .unwrap();
+        storage
+            .create_ws_ticket(WsTicketParams {
+                ticket_hash: "versioned_ticket",
+                user_id: 789,
+                username: "versioned",
+                scopes: &[],
+                roles: &[],
+                session_id: None,
+                expires_at: Utc::now() + Duration::seconds(30),
+            })
+            .await
+            .unwrap();
+
+        storage.bump_token_version(789).await.unwrap();
+        storage.bump_token_version(789).await.unwrap();
+        assert_eq!(storage.get_token_version(789).await.unwrap(), Some(2));
+        let user = storage.get_user(789, key).await.unwrap().unwrap();
+        assert_eq!(user.token_version, 2);
+
+        // Sessions, refresh tokens and WebSocket tickets from before the bump are revoked
+        assert!(storage
+            .consume_ws_ticket("versioned_ticket")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!storage
+            .get_session(session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_active());
+        assert!(matches!(
+            storage
+                .consume_refresh_token("versioned_hash")
+                .await
+                .unwrap(),
+            RefreshTokenStatus::Invalid
+        ));
+    }
+
     #[tokio::test]
     async fn test_memory_storage_entitlements() {
         let storage = MemoryStorage::new();
@@ -290,6 +762,367 @@ mod tests {
             .unwrap();
 
         assert_eq!(storage.entitlement_count(), 1);
+
+        let entitlements = storage.get_user_entitlements(123).await.unwrap();
+        assert_eq!(entitlements.len(), 1);
+        assert_eq!(entitlements[0].sku_id, 456);
+        assert!(entitlements[0].is_active());
+        assert!(storage.get_user_entitlements(999).await.unwrap().is_empty());
+
+        assert_eq!(
+            storage.get_entitlement(1).await.unwrap().map(|e| e.sku_id),
+            Some(456)
+        );
+        assert!(storage
+            .get_entitlement_deleted_at(1)
+            .await
+            .unwrap()
+            .is_none());
+
+        storage.delete_entitlement(1).await.unwrap();
+        let deleted_at = storage.get_entitlement_deleted_at(1).await.unwrap();
+        assert!(deleted_at.is_some());
+        storage.delete_entitlement(1).await.unwrap();
+        assert_eq!(storage.entitlement_count(), 0);
+        assert!(storage.get_entitlement(1).await.unwrap().is_none());
+        assert_eq!(
+            storage.get_entitlement_deleted_at(1).await.unwrap(),
+            deleted_at
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_subscription_decisions() {
+        let storage = MemoryStorage::new();
+        let decision = |user_id, action| SubscriptionDecision {
+            user_id,
+            action,
+            previous_tier: SubscriptionTier::Free,
+            previous_source: None,
+            tier: SubscriptionTier::Premium,
+            source: Some(SubscriptionSource::Discord),
+            expires_at: None,
+            reason: "test".to_string(),
+            decided_at: Utc::now(),
+        };
+
+        for (user_id, action) in [
+            (1, SubscriptionAction::Granted),
+            (2, SubscriptionAction::Granted),
+            (1, SubscriptionAction::Downgraded),
+        ] {
+            storage
+                .record_subscription_decision(&decision(user_id, action))
+                .await
+                .unwrap();
+        }
+
+        let decisions = storage.get_subscription_decisions(1, 10).await.unwrap();
+        let actions: Vec<_> = decisions.iter().map(|d| d.action).collect();
+        assert_eq!(
+            actions,
+            vec![SubscriptionAction::Downgraded, SubscriptionAction::Granted]
+        );
+        assert_eq!(
+            storage
+                .get_subscription_decisions(1, 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_list_users() {
+        let storage = MemoryStorage::new();
+        for (user_id, username) in [(1, "alice"), (2, "bob"), (3, "alicia")] {
+            storage
+                .upsert_user(
+                    UserUpsertParams {
+                        user_id,
+                        username,
+                        global_name: None,
+                        avatar_url: None,
+                        refresh_token: Some("secret"),
+                        token_expires_at: None,
+                        granted_scopes: None,
+                    },
+                    "unused",
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .update_subscription(
+                2,
+                SubscriptionTier::Premium,
+                SubscriptionSource::Manual,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let page = |search, tier, limit, offset| UserListParams {
+            search,
+            tier,
+            limit,
+            offset,
+        };
+        let ids = |users: Vec<User>| users.iter().map(|u| u.user_id).collect::<Vec<_>>();
+
+        let all = storage.list_users(page(None, None, 10, 0)).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.iter().all(|user| user.refresh_token.is_none()));
+
+        let alices = storage
+            .list_users(page(Some("ALI"), None, 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(alices.len(), 2);
+
+        let premium = storage
+            .list_users(page(None, Some(SubscriptionTier::Premium), 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(ids(premium), vec![2]);
+
+        let first = storage.list_users(page(None, None, 2, 0)).await.unwrap();
+        let rest = storage.list_users(page(None, None, 2, 2)).await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(rest.len(), 1);
+        assert!(!ids(first).contains(&rest[0].user_id));
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_refresh_token_rotation() {
+        let storage = MemoryStorage::new();
+        let family_id = Uuid::new_v4();
+        let expires_at = Utc::now() + Duration::days(30);
+
+        storage
+            .store_refresh_token(RefreshTokenParams {
+                token_hash: "hash-1",
+                user_id: 123,
+                family_id,
+                expires_at,
+            })
+            .await
+            .unwrap();
+        storage
+            .store_refresh_token(RefreshTokenParams {
+                token_hash: "hash-2",
+                user_id: 123,
+                family_id,
+                expires_at,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.consume_refresh_token("hash-1").await.unwrap(),
+            RefreshTokenStatus::Valid {
+                user_id: 123,
+                family_id
+            }
+        );
+        // Replaying a used token is reported as reuse
+        assert_eq!(
+            storage.consume_refresh_token("hash-1").await.unwrap(),
+            RefreshTokenStatus::Reused {
+                user_id: 123,
+                family_id
+            }
+        );
+
+        // Revoking the family invalidates the rotated token too
+        storage
+            .revoke_refresh_token_family(family_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.consume_refresh_token("hash-2").await.unwrap(),
+            RefreshTokenStatus::Invalid
+        );
+        assert_eq!(
+            storage.consume_refresh_token("unknown").await.unwrap(),
+            RefreshTokenStatus::Invalid
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_refresh_token_expiry_and_user_revocation() {
+        let storage = MemoryStorage::new();
+
+        storage
+            .store_refresh_token(RefreshTokenParams {
+                token_hash: "expired",
+                user_id: 123,
+                family_id: Uuid::new_v4(),
+                expires_at: Utc::now() - Duration::seconds(1),
+            })
+            .await
+            .unwrap();
+        storage
+            .store_refresh_token(RefreshTokenParams {
+                token_hash: "active",
+                user_id: 123,
+                family_id: Uuid::new_v4(),
+                expires_at: Utc::now() + Duration::days(30),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.consume_refresh_token("expired").await.unwrap(),
+            RefreshTokenStatus::Invalid
+        );
+
+        storage.revoke_user_refresh_tokens(123).await.unwrap();
+        assert_eq!(
+            storage.consume_refresh_token("active").await.unwrap(),
+            RefreshTokenStatus::Invalid
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_sessions() {
+        let storage = MemoryStorage::new();
+        let expires_at = Utc::now() + Duration::days(30);
+        let laptop = Uuid::new_v4();
+        let phone = Uuid::new_v4();
+
+        for (session_id, user_agent) in [(laptop, "laptop"), (phone, "phone")] {
+            storage
+                .create_session(SessionCreateParams {
+                    session_id,
+                    user_id: 123,
+                    user_agent: Some(user_agent),
+                    ip_address: Some("203.0.113.7"),
+                    expires_at,
+                })
+                .await
+                .unwrap();
+        }
+
+        storage.touch_session(laptop, None).await.unwrap();
+        let sessions = storage.list_user_sessions(123).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].session_id, laptop, "Most recently seen first");
+
+        storage.revoke_session(phone).await.unwrap();
+        assert!(!storage
+            .get_session(phone)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_active());
+        assert_eq!(storage.list_user_sessions(123).await.unwrap().len(), 1);
+
+        storage.revoke_user_sessions(123).await.unwrap();
+        assert!(storage.list_user_sessions(123).await.unwrap().is_empty());
+        assert!(storage.get_session(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_ws_tickets() {
+        let storage = MemoryStorage::new();
+        let scopes = vec!["identify".to_string()];
+        let params = |ticket_hash, expires_at| WsTicketParams {
+            ticket_hash,
+            user_id: 42,
+            username: "socket_user",
+            scopes: &scopes,
+            roles: &[],
+            session_id: None,
+            expires_at,
+        };
+
+        storage
+            .create_ws_ticket(params("fresh", Utc::now() + Duration::seconds(30)))
+            .await
+            .unwrap();
+        storage
+            .create_ws_ticket(params("stale", Utc::now() - Duration::seconds(1)))
+            .await
+            .unwrap();
+
+        let ticket = storage.consume_ws_ticket("fresh").await.unwrap().unwrap();
+        assert_eq!(ticket.user_id, 42);
+        assert_eq!(ticket.scopes, scopes);
+
+        // Single use, and expired tickets are never returned
+        assert!(storage.consume_ws_ticket("fresh").await.unwrap().is_none());
+        assert!(storage.consume_ws_ticket("stale").await.unwrap().is_none());
+        assert!(storage
+            .consume_ws_ticket("unknown")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_oauth_states() {
+        let storage = MemoryStorage::new();
+        let expires_at = Utc::now() + Duration::seconds(600);
+
+        assert!(storage
+            .consume_oauth_state("nonce", expires_at)
+            .await
+            .unwrap());
+        assert!(!storage
+            .consume_oauth_state("nonce", expires_at)
+            .await
+            .unwrap());
+        assert!(storage
+            .consume_oauth_state("other", expires_at)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_roles() {
+        let storage = MemoryStorage::new();
+        storage
+            .upsert_user(
+                UserUpsertParams {
+                    user_id: 7,
+                    username: "staff",
+                    global_name: None,
+                    avatar_url: None,
+                    refresh_token: None,
+                    token_expires_at: None,
+                    granted_scopes: None,
+                },
+                "unused",
+            )
+            .await
+            .unwrap();
+
+        let support = vec!["users:read".to_string(), "sessions:revoke".to_string()];
+        storage.upsert_role("support", &support).await.unwrap();
+        storage.grant_role(7, "support").await.unwrap();
+        storage.grant_role(7, "support").await.unwrap();
+        assert_eq!(storage.get_user_roles(7).await.unwrap(), vec!["support"]);
+        assert_eq!(
+            storage.get_user_permissions(7).await.unwrap(),
+            vec!["sessions:revoke", "users:read"]
+        );
+
+        // Unknown roles and users are rejected like foreign keys in SQL
+        assert!(storage.grant_role(7, "missing").await.is_err());
+        assert!(storage.grant_role(8, "support").await.is_err());
+
+        storage.grant_role(7, ADMIN_ROLE).await.unwrap();
+        assert_eq!(
+            storage.get_user_permissions(7).await.unwrap(),
+            vec!["*", "sessions:revoke", "users:read"]
+        );
+
+        storage.revoke_role(7, "support").await.unwrap();
+        storage.revoke_role(7, "never-granted").await.unwrap();
+        assert_eq!(storage.get_user_roles(7).await.unwrap(), vec![ADMIN_ROLE]);
+        assert_eq!(storage.get_user_permissions(7).await.unwrap(), vec!["*"]);
     }
 
     #[tokio::test]
@@ -306,6 +1139,7 @@ mod tests {
                     avatar_url: None,
                     refresh_token: None,
                     token_expires_at: None,
+                    granted_scopes: None,
                 },
                 key,
             )
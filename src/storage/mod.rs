@@ -3,17 +3,25 @@
 //! This module provides a trait-based storage abstraction with two implementations:
 //! - `SqlxStorage`: `PostgreSQL` storage via `SQLx` (feature: `sqlx-storage`)
 //! - `MemoryStorage`: In-memory storage for testing (feature: `memory-storage`)
+//!
+//! `PublishingStorage` wraps either one and publishes account events for its writes.
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use uuid::Uuid;
 
 use crate::{
     error::{Result, StorageError},
     models::{
-        EntitlementUpsertParams, SubscriptionSource, SubscriptionTier, User, UserUpsertParams,
+        Entitlement, EntitlementUpsertParams, RefreshTokenParams, RefreshTokenStatus, Session,
+        SessionCreateParams, SubscriptionDecision, SubscriptionSource, SubscriptionTier, User,
+        UserListParams, UserUpsertParams, WsTicket, WsTicketParams,
     },
 };
 
+mod publishing;
+pub use publishing::PublishingStorage;
+
 #[cfg(feature = "sqlx-storage")]
 mod sqlx_impl;
 #[cfg(feature = "sqlx-storage")]
@@ -38,6 +46,18 @@ pub trait UserStorage: Send + Sync {
     ///     - `StorageError` - If an error occurs during retrieval
     async fn get_user(&self, user_id: i64, encryption_key: &str) -> Result<Option<User>>;
 
+    /// List users matching a search, newest first.
+    ///
+    /// Refresh tokens are not decrypted; every returned user has `refresh_token` set to None.
+    ///
+    /// Parameters:
+    ///     - params: `UserListParams` - Search, tier filter and pagination
+    /// Returns:
+    ///     - `Result<Vec<User>>` - Matching users, at most `params.limit`
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn list_users(&self, params: UserListParams<'_>) -> Result<Vec<User>>;
+
     /// Create or update a user.
     ///
     /// Parameters:
@@ -95,6 +115,72 @@ pub trait UserStorage: Send + Sync {
         source: SubscriptionSource,
         expires_at: Option<DateTime<Utc>>,
     ) -> Result<()>;
+
+    /// Store a newly issued first-party refresh token.
+    ///
+    /// Parameters:
+    ///    - params: `RefreshTokenParams` - Hashed token, owner, family and expiry
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during insert
+    async fn store_refresh_token(&self, params: RefreshTokenParams<'_>) -> Result<()>;
+
+    /// Atomically mark a refresh token as used.
+    ///
+    /// A token can only be consumed once. Consuming an already-used token
+    /// reports `RefreshTokenStatus::Reused` so the caller can revoke its family.
+    /// Parameters:
+    ///    - `token_hash`: &str - SHA-256 hash of the presented token
+    /// Returns:
+    ///    - `Result<RefreshTokenStatus>` - Whether the token was valid, reused or invalid
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn consume_refresh_token(&self, token_hash: &str) -> Result<RefreshTokenStatus>;
+
+    /// Revoke every refresh token in a family.
+    ///
+    /// Parameters:
+    ///    - `family_id`: `Uuid` - Token family to revoke
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn revoke_refresh_token_family(&self, family_id: Uuid) -> Result<()>;
+
+    /// Revoke every refresh token issued to a user.
+    ///
+    /// Parameters:
+    ///    - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn revoke_user_refresh_tokens(&self, user_id: i64) -> Result<()>;
+
+    /// Get a user's current token version without loading the whole user.
+    ///
+    /// Parameters:
+    ///    - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///    - `Result<Option<i32>>` - Token version or None if the user was not found
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during retrieval
+    async fn get_token_version(&self, user_id: i64) -> Result<Option<i32>>;
+
+    /// Increment a user's token version, invalidating every JWT issued before.
+    ///
+    /// Also revokes all of the user's refresh tokens and sessions and deletes
+    /// their unused WebSocket tickets, which were issued under the old version,
+    /// so none of them can mint a new JWT or open a connection.
+    ///
+    /// Parameters:
+    ///    - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn bump_token_version(&self, user_id: i64) -> Result<()>;
 }
 
 /// Storage trait for entitlement operations.
@@ -109,15 +195,251 @@ pub trait EntitlementStorage: Send + Sync {
     /// Errors:
     ///    - `StorageError` - If an error occurs during upsert
     async fn upsert_entitlement(&self, params: EntitlementUpsertParams) -> Result<()>;
+
+    /// Get a user's entitlements, newest first.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///     - `Result<Vec<Entitlement>>` - The user's entitlements, empty if they have none
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn get_user_entitlements(&self, user_id: i64) -> Result<Vec<Entitlement>>;
+
+    /// Get a stored entitlement by ID.
+    ///
+    /// Parameters:
+    ///     - `entitlement_id`: `i64` - Discord entitlement ID
+    /// Returns:
+    ///     - `Result<Option<Entitlement>>` - The entitlement or None if it is not stored
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn get_entitlement(&self, entitlement_id: i64) -> Result<Option<Entitlement>>;
+
+    /// Delete an entitlement Discord no longer reports, e.g. after a refund.
+    ///
+    /// The deletion is remembered, see `get_entitlement_deleted_at`.
+    ///
+    /// Parameters:
+    ///     - `entitlement_id`: `i64` - Discord entitlement ID
+    /// Returns:
+    ///     - `Result<()>` - Success or error; deleting an unknown entitlement is not an error
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during delete
+    async fn delete_entitlement(&self, entitlement_id: i64) -> Result<()>;
+
+    /// Get when an entitlement was first deleted, so late events for it can be ignored.
+    ///
+    /// Parameters:
+    ///     - `entitlement_id`: `i64` - Discord entitlement ID
+    /// Returns:
+    ///     - `Result<Option<DateTime<Utc>>>` - Deletion time or None if it was never deleted
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn get_entitlement_deleted_at(
+        &self,
+        entitlement_id: i64,
+    ) -> Result<Option<DateTime<Utc>>>;
+
+    /// Record a subscription decision made while reconciling entitlements.
+    ///
+    /// Parameters:
+    ///     - decision: `&SubscriptionDecision` - The decision to record
+    /// Returns:
+    ///     - `Result<()>` - Success or error
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during insert
+    async fn record_subscription_decision(&self, decision: &SubscriptionDecision) -> Result<()>;
+
+    /// Get a user's recorded subscription decisions, newest first.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    ///     - limit: `i64` - Maximum number of decisions to return
+    /// Returns:
+    ///     - `Result<Vec<SubscriptionDecision>>` - The user's most recent decisions
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn get_subscription_decisions(
+        &self,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SubscriptionDecision>>;
+}
+
+/// Storage trait for login session operations.
+#[async_trait]
+pub trait SessionStorage: Send + Sync {
+    /// Create a session for a new login.
+    ///
+    /// Parameters:
+    ///    - params: `SessionCreateParams` - Session ID, owner, client details and expiry
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during insert
+    async fn create_session(&self, params: SessionCreateParams<'_>) -> Result<()>;
+
+    /// Get a session by ID, whether or not it is still active.
+    ///
+    /// Parameters:
+    ///    - `session_id`: `Uuid` - Session ID
+    /// Returns:
+    ///    - `Result<Option<Session>>` - Retrieved session or None if not found
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during retrieval
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>>;
+
+    /// List a user's active sessions, most recently seen first.
+    ///
+    /// Parameters:
+    ///    - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///    - `Result<Vec<Session>>` - Active sessions
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during retrieval
+    async fn list_user_sessions(&self, user_id: i64) -> Result<Vec<Session>>;
+
+    /// Record that a session was used, optionally extending its expiry.
+    ///
+    /// Parameters:
+    ///    - `session_id`: `Uuid` - Session ID
+    ///    - `expires_at`: `Option<DateTime<Utc>>` - New expiry, or None to keep it
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn touch_session(
+        &self,
+        session_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()>;
+
+    /// Revoke a single session.
+    ///
+    /// Parameters:
+    ///    - `session_id`: `Uuid` - Session ID
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn revoke_session(&self, session_id: Uuid) -> Result<()>;
+
+    /// Revoke every session belonging to a user.
+    ///
+    /// Parameters:
+    ///    - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during update
+    async fn revoke_user_sessions(&self, user_id: i64) -> Result<()>;
+
+    /// Store a newly issued WebSocket ticket.
+    ///
+    /// Parameters:
+    ///    - params: `WsTicketParams` - Hashed ticket, owner and expiry
+    /// Returns:
+    ///    - `Result<()>` - Success or error
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during insert
+    async fn create_ws_ticket(&self, params: WsTicketParams<'_>) -> Result<()>;
+
+    /// Atomically remove a WebSocket ticket and return it if it had not expired.
+    ///
+    /// A ticket can only be consumed once; later attempts return None.
+    /// Parameters:
+    ///    - `ticket_hash`: &str - SHA-256 hash of the presented ticket
+    /// Returns:
+    ///    - `Result<Option<WsTicket>>` - The ticket, or None if unknown, used or expired
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during delete
+    async fn consume_ws_ticket(&self, ticket_hash: &str) -> Result<Option<WsTicket>>;
+
+    /// Record that a signed OAuth `state` has been redeemed.
+    ///
+    /// A state can only be redeemed once; later attempts return false. Records
+    /// are kept until `expires_at`, after which the state is rejected anyway.
+    /// Parameters:
+    ///    - nonce: &str - Nonce carried in the state
+    ///    - `expires_at`: `DateTime<Utc>` - When the state expires
+    /// Returns:
+    ///    - `Result<bool>` - True if this is the first redemption
+    /// Errors:
+    ///    - `StorageError` - If an error occurs during insert
+    async fn consume_oauth_state(&self, nonce: &str, expires_at: DateTime<Utc>) -> Result<bool>;
+}
+
+/// Storage trait for roles and the permissions they grant.
+#[async_trait]
+pub trait RoleStorage: Send + Sync {
+    /// Create a role, or replace the permissions of an existing one.
+    ///
+    /// Parameters:
+    ///     - `name`: `&str` - Role name
+    ///     - `permissions`: `&[String]` - Permissions the role grants; `*` grants all
+    /// Returns:
+    ///     - `Result<()>` - Success or error
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during upsert
+    async fn upsert_role(&self, name: &str, permissions: &[String]) -> Result<()>;
+
+    /// Grant a role to a user. Granting a role the user already has is a no-op.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    ///     - `role`: `&str` - Name of an existing role
+    /// Returns:
+    ///     - `Result<()>` - Success or error
+    /// Errors:
+    ///     - `StorageError` - If the user or role does not exist, or the grant fails
+    async fn grant_role(&self, user_id: i64, role: &str) -> Result<()>;
+
+    /// Revoke a role from a user. Revoking a role the user lacks is a no-op.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    ///     - `role`: `&str` - Role name
+    /// Returns:
+    ///     - `Result<()>` - Success or error
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during revocation
+    async fn revoke_role(&self, user_id: i64, role: &str) -> Result<()>;
+
+    /// Get the names of a user's roles, sorted.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///     - `Result<Vec<String>>` - Role names, empty if the user has none
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>>;
+
+    /// Get every permission granted to a user through their roles, sorted and deduplicated.
+    ///
+    /// Parameters:
+    ///     - `user_id`: `i64` - Discord user ID
+    /// Returns:
+    ///     - `Result<Vec<String>>` - Permissions, empty if the user has none
+    /// Errors:
+    ///     - `StorageError` - If an error occurs during retrieval
+    async fn get_user_permissions(&self, user_id: i64) -> Result<Vec<String>>;
 }
 
 /// Combined storage trait for convenience.
 ///
 /// This trait is object-safe and can be used with `Box<dyn Storage>` for
 /// dynamic dispatch, or with concrete types for static dispatch.
-pub trait Storage: UserStorage + EntitlementStorage + Send + Sync {}
+pub trait Storage:
+    UserStorage + EntitlementStorage + SessionStorage + RoleStorage + Send + Sync
+{
+}
 
-impl<T: UserStorage + EntitlementStorage + Send + Sync> Storage for T {}
+impl<T: UserStorage + EntitlementStorage + SessionStorage + RoleStorage + Send + Sync> Storage
+    for T
+{
+}
 
 /// Helper function to create a storage error from a string.
 ///
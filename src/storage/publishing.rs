@@ -0,0 +1,299 @@
+//! Storage decorator that publishes account events for its writes.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{EntitlementStorage, RoleStorage, SessionStorage, Storage, UserStorage};
+use crate::{
+    error::Result,
+    events::{AccountEvent, EventBus},
+    models::{
+        Entitlement, EntitlementUpsertParams, RefreshTokenParams, RefreshTokenStatus, Session,
+        SessionCreateParams, SubscriptionDecision, SubscriptionSource, SubscriptionTier, User,
+        UserListParams, UserUpsertParams, WsTicket, WsTicketParams,
+    },
+};
+
+/// Wraps a storage backend and publishes an [`AccountEvent`] after each
+/// successful write that changes what a user's clients display:
+///
+/// - `update_subscription` publishes `SubscriptionChanged`
+/// - `upsert_user` publishes `ProfileUpdated`
+/// - `bump_token_version` publishes `ForcedLogout` for every session, since
+///   it revokes them all
+///
+/// `AppState` wraps its storage in this automatically.
+pub struct PublishingStorage {
+    inner: Box<dyn Storage>,
+    events: EventBus,
+}
+
+impl PublishingStorage {
+    /// Wrap a storage backend, publishing to the given bus.
+    pub fn new(inner: impl Storage + 'static, events: EventBus) -> Self {
+        Self {
+            inner: Box::new(inner),
+            events,
+        }
+    }
+}
+
+#[async_trait]
+impl UserStorage for PublishingStorage {
+    async fn get_user(&self, user_id: i64, encryption_key: &str) -> Result<Option<User>> {
+        self.inner.get_user(user_id, encryption_key).await
+    }
+
+    async fn list_users(&self, params: UserListParams<'_>) -> Result<Vec<User>> {
+        self.inner.list_users(params).await
+    }
+
+    async fn upsert_user(&self, params: UserUpsertParams<'_>, encryption_key: &str) -> Result<()> {
+        let event = AccountEvent::ProfileUpdated {
+            username: params.username.to_string(),
+            global_name: params.global_name.map(str::to_string),
+            avatar_url: params.avatar_url.map(str::to_string),
+        };
+        let user_id = params.user_id;
+
+        self.inner.upsert_user(params, encryption_key).await?;
+        self.events.publish(user_id, event);
+        Ok(())
+    }
+
+    async fn update_refresh_token(
+        &self,
+        user_id: i64,
+        refresh_token: &str,
+        token_expires_at: DateTime<Utc>,
+        encryption_key: &str,
+    ) -> Result<()> {
+        self.inner
+            .update_refresh_token(user_id, refresh_token, token_expires_at, encryption_key)
+            .await
+    }
+
+    async fn clear_user_tokens(&self, user_id: i64) -> Result<()> {
+        self.inner.clear_user_tokens(user_id).await
+    }
+
+    async fn update_subscription(
+        &self,
+        user_id: i64,
+        tier: SubscriptionTier,
+        source: SubscriptionSource,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        self.inner
+            .update_subscription(user_id, tier, source, expires_at)
+            .await?;
+        self.events.publish(
+            user_id,
+            AccountEvent::SubscriptionChanged {
+                tier,
+                source,
+                expires_at,
+            },
+        );
+        Ok(())
+    }
+
+    async fn store_refresh_token(&self, params: RefreshTokenParams<'_>) -> Result<()> {
+        self.inner.store_refresh_token(params).await
+    }
+
+    async fn consume_refresh_token(&self, token_hash: &str) -> Result<RefreshTokenStatus> {
+        self.inner.consume_refresh_token(token_hash).await
+    }
+
+    async fn revoke_refresh_token_family(&self, family_id: Uuid) -> Result<()> {
+        self.inner.revoke_refresh_token_family(family_id).await
+    }
+
+    async fn revoke_user_refresh_tokens(&self, user_id: i64) -> Result<()> {
+        self.inner.revoke_user_refresh_tokens(user_id).await
+    }
+
+    async fn get_token_version(&self, user_id: i64) -> Result<Option<i32>> {
+        self.inner.get_token_version(user_id).await
+    }
+
+    async fn bump_token_version(&self, user_id: i64) -> Result<()> {
+        self.inner.bump_token_version(user_id).await?;
+        self.events
+            .publish(user_id, AccountEvent::ForcedLogout { session_id: None });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EntitlementStorage for PublishingStorage {
+    async fn upsert_entitlement(&self, params: EntitlementUpsertParams) -> Result<()> {
+        self.inner.upsert_entitlement(params).await
+    }
+
+    async fn get_user_entitlements(&self, user_id: i64) -> Result<Vec<Entitlement>> {
+        self.inner.get_user_entitlements(user_id).await
+    }
+
+    async fn get_entitlement(&self, entitlement_id: i64) -> Result<Option<Entitlement>> {
+        self.inner.get_entitlement(entitlement_id).await
+    }
+
+    async fn delete_entitlement(&self, entitlement_id: i64) -> Result<()> {
+        self.inner.delete_entitlement(entitlement_id).await
+    }
+
+    async fn get_entitlement_deleted_at(
+        &self,
+        entitlement_id: i64,
+    ) -> Result<Option<DateTime<Utc>>> {
+        self.inner.get_entitlement_deleted_at(entitlement_id).await
+    }
+
+    async fn record_subscription_decision(&self, decision: &SubscriptionDecision) -> Result<()> {
+        self.inner.record_subscription_decision(decision).await
+    }
+
+    async fn get_subscription_decisions(
+        &self,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SubscriptionDecision>> {
+        self.inner.get_subscription_decisions(user_id, limit).await
+    }
+}
+
+#[async_trait]
+impl SessionStorage for PublishingStorage {
+    async fn create_session(&self, params: SessionCreateParams<'_>) -> Result<()> {
+        self.inner.create_session(params).await
+    }
+
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>> {
+        self.inner.get_session(session_id).await
+    }
+
+    async fn list_user_sessions(&self, user_id: i64) -> Result<Vec<Session>> {
+        self.inner.list_user_sessions(user_id).await
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        self.inner.touch_session(session_id, expires_at).await
+    }
+
+    async fn revoke_session(&self, session_id: Uuid) -> Result<()> {
+        self.inner.revoke_session(session_id).await
+    }
+
+    async fn revoke_user_sessions(&self, user_id: i64) -> Result<()> {
+        self.inner.revoke_user_sessions(user_id).await
+    }
+
+    async fn create_ws_ticket(&self, params: WsTicketParams<'_>) -> Result<()> {
+        self.inner.create_ws_ticket(params).await
+    }
+
+    async fn consume_ws_ticket(&self, ticket_hash: &str) -> Result<Option<WsTicket>> {
+        self.inner.consume_ws_ticket(ticket_hash).await
+    }
+
+    async fn consume_oauth_state(&self, nonce: &str, expires_at: DateTime<Utc>) -> Result<bool> {
+        self.inner.consume_oauth_state(nonce, expires_at).await
+    }
+}
+
+#[async_trait]
+impl RoleStorage for PublishingStorage {
+    async fn upsert_role(&self, name: &str, permissions: &[String]) -> Result<()> {
+        self.inner.upsert_role(name, permissions).await
+    }
+
+    async fn grant_role(&self, user_id: i64, role: &str) -> Result<()> {
+        self.inner.grant_role(user_id, role).await
+    }
+
+    async fn revoke_role(&self, user_id: i64, role: &str) -> Result<()> {
+        self.inner.revoke_role(user_id, role).await
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>> {
+        self.inner.get_user_roles(user_id).await
+    }
+
+    async fn get_user_permissions(&self, user_id: i64) -> Result<Vec<String>> {
+        self.inner.get_user_permissions(user_id).await
+    }
+}
+
+#[cfg(all(test, feature = "memory-storage"))]
+mod tests {
+    use super::*;
+    use crate::{events::UserEvent, storage::MemoryStorage};
+
+    #[tokio::test]
+    async fn test_publishing_storage_publishes_writes() {
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let storage = PublishingStorage::new(MemoryStorage::new(), events);
+
+        storage
+            .upsert_user(
+                UserUpsertParams {
+                    user_id: 42,
+                    username: "alice",
+                    global_name: Some("Alice"),
+                    avatar_url: None,
+                    refresh_token: None,
+                    token_expires_at: None,
+                    granted_scopes: None,
+                },
+                "",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            UserEvent {
+                user_id: 42,
+                event: AccountEvent::ProfileUpdated {
+                    username: "alice".to_string(),
+                    global_name: Some("Alice".to_string()),
+                    avatar_url: None,
+                },
+            }
+        );
+
+        storage
+            .update_subscription(
+                42,
+                SubscriptionTier::Premium,
+                SubscriptionSource::Manual,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            receiver.try_recv().unwrap().event,
+            AccountEvent::SubscriptionChanged {
+                tier: SubscriptionTier::Premium,
+                ..
+            }
+        ));
+
+        storage.bump_token_version(42).await.unwrap();
+        assert_eq!(
+            receiver.try_recv().unwrap().event,
+            AccountEvent::ForcedLogout { session_id: None }
+        );
+
+        // Reads publish nothing.
+        storage.get_user(42, "").await.unwrap();
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -3,14 +3,17 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
     encryption,
     error::{Result, StorageError},
     models::{
-        EntitlementUpsertParams, SubscriptionSource, SubscriptionTier, User, UserUpsertParams,
+        Entitlement, EntitlementUpsertParams, RefreshTokenParams, RefreshTokenStatus, Session,
+        SessionCreateParams, SubscriptionAction, SubscriptionDecision, SubscriptionSource,
+        SubscriptionTier, User, UserListParams, UserUpsertParams, WsTicket, WsTicketParams,
     },
-    storage::{EntitlementStorage, UserStorage},
+    storage::{EntitlementStorage, RoleStorage, SessionStorage, UserStorage},
 };
 
 /// `SQLx` `PostgreSQL` storage backend.
@@ -21,19 +24,19 @@ pub struct SqlxStorage {
 
 impl SqlxStorage {
     /// Create a new `SQLx` storage with the given connection pool.
-    #[must_use] 
+    #[must_use]
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
 
     /// Get a reference to the underlying connection pool.
-    #[must_use] 
+    #[must_use]
     pub fn pool(&self) -> &PgPool {
         &self.pool
     }
 
     /// Run database migrations.
-    /// 
+    ///
     /// # Errors
     ///    - Returns `StorageError` if migration fails.
     pub async fn migrate(&self) -> Result<()> {
@@ -43,6 +46,56 @@ impl SqlxStorage {
             .map_err(|e| StorageError::Database(e.into()))?;
         Ok(())
     }
+
+    /// Re-encrypt every stored Discord refresh token with a new encryption key.
+    ///
+    /// Runs in a single transaction, so either every token is re-encrypted or
+    /// none is. Returns the number of tokens re-encrypted.
+    ///
+    /// # Errors
+    ///    - Returns an error if `new_key` is not a valid key, a token cannot be
+    ///      decrypted with `old_key`, or a query fails.
+    pub async fn rotate_encryption_key(&self, old_key: &str, new_key: &str) -> Result<u64> {
+        // Fail before touching any row if the new key is unusable
+        encryption::encrypt("", new_key)?;
+
+        let mut tx = self.pool.begin().await.map_err(StorageError::Database)?;
+        let rows = sqlx::query_as::<_, (i64, String)>(
+            r"
+            SELECT user_id, refresh_token
+            FROM users
+            WHERE refresh_token IS NOT NULL
+            FOR UPDATE
+            ",
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        for (user_id, encrypted) in &rows {
+            let token = encryption::decrypt(encrypted, old_key).map_err(|e| {
+                StorageError::Other(format!(
+                    "failed to decrypt refresh token of user {user_id}: {e}"
+                ))
+            })?;
+
+            sqlx::query(
+                r"
+                UPDATE users
+                SET refresh_token = $2
+                WHERE user_id = $1
+                ",
+            )
+            .bind(user_id)
+            .bind(encryption::encrypt(&token, new_key)?)
+            .execute(&mut *tx)
+            .await
+            .map_err(StorageError::Database)?;
+        }
+
+        tx.commit().await.map_err(StorageError::Database)?;
+        Ok(rows.len() as u64)
+    }
 }
 
 #[async_trait]
@@ -52,7 +105,7 @@ impl UserStorage for SqlxStorage {
             r"
             SELECT
                 user_id, username, global_name, avatar_url,
-                refresh_token, token_expires_at,
+                refresh_token, token_expires_at, granted_scopes, token_version,
                 subscription_tier, subscription_source, subscription_expires_at,
                 created_at, updated_at
             FROM users
@@ -66,33 +119,60 @@ impl UserStorage for SqlxStorage {
 
         match row {
             Some(row) => {
-                let refresh_token = match row.refresh_token {
-                    Some(encrypted) => Some(
-                        encryption::decrypt(&encrypted, encryption_key).map_err(|e| {
+                let refresh_token = match &row.refresh_token {
+                    Some(encrypted) => {
+                        Some(encryption::decrypt(encrypted, encryption_key).map_err(|e| {
                             StorageError::Other(format!("failed to decrypt refresh token: {e}"))
-                        })?,
-                    ),
+                        })?)
+                    }
                     None => None,
                 };
 
-                Ok(Some(User {
-                    user_id: row.user_id,
-                    username: row.username,
-                    global_name: row.global_name,
-                    avatar_url: row.avatar_url,
-                    refresh_token,
-                    token_expires_at: row.token_expires_at,
-                    subscription_tier: row.subscription_tier,
-                    subscription_source: row.subscription_source,
-                    subscription_expires_at: row.subscription_expires_at,
-                    created_at: row.created_at,
-                    updated_at: row.updated_at,
-                }))
+                Ok(Some(row.into_user(refresh_token)))
             }
             None => Ok(None),
         }
     }
 
+    async fn list_users(&self, params: UserListParams<'_>) -> Result<Vec<User>> {
+        // Match the search literally, not as a LIKE pattern
+        let pattern = params.search.map(|search| {
+            let escaped = search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{escaped}%")
+        });
+
+        let rows = sqlx::query_as::<_, UserRow>(
+            r"
+            SELECT
+                user_id, username, global_name, avatar_url,
+                NULL AS refresh_token, token_expires_at, granted_scopes, token_version,
+                subscription_tier, subscription_source, subscription_expires_at,
+                created_at, updated_at
+            FROM users
+            WHERE ($1::TEXT IS NULL
+                OR username ILIKE $1
+                OR global_name ILIKE $1
+                OR user_id::TEXT = $2)
+              AND ($3::TEXT IS NULL OR subscription_tier = $3)
+            ORDER BY created_at DESC, user_id DESC
+            LIMIT $4 OFFSET $5
+            ",
+        )
+        .bind(pattern)
+        .bind(params.search)
+        .bind(params.tier)
+        .bind(params.limit)
+        .bind(params.offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(rows.into_iter().map(|row| row.into_user(None)).collect())
+    }
+
     async fn upsert_user(&self, params: UserUpsertParams<'_>, encryption_key: &str) -> Result<()> {
         let encrypted_token = match params.refresh_token {
             Some(token) => Some(encryption::encrypt(token, encryption_key).map_err(|e| {
@@ -103,14 +183,15 @@ impl UserStorage for SqlxStorage {
 
         sqlx::query(
             r"
-            INSERT INTO users (user_id, username, global_name, avatar_url, refresh_token, token_expires_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO users (user_id, username, global_name, avatar_url, refresh_token, token_expires_at, granted_scopes)
+            VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7::TEXT[], '{}'))
             ON CONFLICT (user_id) DO UPDATE SET
                 username = EXCLUDED.username,
                 global_name = EXCLUDED.global_name,
                 avatar_url = EXCLUDED.avatar_url,
                 refresh_token = COALESCE(EXCLUDED.refresh_token, users.refresh_token),
                 token_expires_at = COALESCE(EXCLUDED.token_expires_at, users.token_expires_at),
+                granted_scopes = COALESCE($7::TEXT[], users.granted_scopes),
                 updated_at = NOW()
             ",
         )
@@ -120,6 +201,7 @@ impl UserStorage for SqlxStorage {
         .bind(params.avatar_url)
         .bind(encrypted_token)
         .bind(params.token_expires_at)
+        .bind(params.granted_scopes)
         .execute(&self.pool)
         .await
         .map_err(StorageError::Database)?;
@@ -194,6 +276,158 @@ impl UserStorage for SqlxStorage {
 
         Ok(())
     }
+
+    async fn store_refresh_token(&self, params: RefreshTokenParams<'_>) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO refresh_tokens (token_hash, user_id, family_id, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ",
+        )
+        .bind(params.token_hash)
+        .bind(params.user_id)
+        .bind(params.family_id)
+        .bind(params.expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn consume_refresh_token(&self, token_hash: &str) -> Result<RefreshTokenStatus> {
+        // Only one concurrent caller can flip `used_at`, so a token is never valid twice
+        let consumed = sqlx::query_as::<_, (i64, Uuid)>(
+            r"
+            UPDATE refresh_tokens
+            SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            RETURNING user_id, family_id
+            ",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        if let Some((user_id, family_id)) = consumed {
+            return Ok(RefreshTokenStatus::Valid { user_id, family_id });
+        }
+
+        let reused = sqlx::query_as::<_, (i64, Uuid)>(
+            r"
+            SELECT user_id, family_id
+            FROM refresh_tokens
+            WHERE token_hash = $1 AND used_at IS NOT NULL
+            ",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(match reused {
+            Some((user_id, family_id)) => RefreshTokenStatus::Reused { user_id, family_id },
+            None => RefreshTokenStatus::Invalid,
+        })
+    }
+
+    async fn revoke_refresh_token_family(&self, family_id: Uuid) -> Result<()> {
+        sqlx::query(
+            r"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE family_id = $1 AND revoked_at IS NULL
+            ",
+        )
+        .bind(family_id)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn revoke_user_refresh_tokens(&self, user_id: i64) -> Result<()> {
+        sqlx::query(
+            r"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn get_token_version(&self, user_id: i64) -> Result<Option<i32>> {
+        let version = sqlx::query_scalar::<_, i32>(
+            r"
+            SELECT token_version
+            FROM users
+            WHERE user_id = $1
+            ",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(version)
+    }
+
+    async fn bump_token_version(&self, user_id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(StorageError::Database)?;
+        sqlx::query(
+            r"
+            UPDATE users
+            SET token_version = token_version + 1
+            WHERE user_id = $1
+            ",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        // Everything issued under the old version goes with it
+        sqlx::query(
+            r"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        sqlx::query(
+            r"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        sqlx::query("DELETE FROM ws_tickets WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(StorageError::Database)?;
+
+        tx.commit().await.map_err(StorageError::Database)?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -223,6 +457,435 @@ impl EntitlementStorage for SqlxStorage {
 
         Ok(())
     }
+
+    async fn get_user_entitlements(&self, user_id: i64) -> Result<Vec<Entitlement>> {
+        let rows = sqlx::query_as::<_, EntitlementRow>(
+            r"
+            SELECT
+                entitlement_id, user_id, sku_id, entitlement_type, is_test, consumed,
+                starts_at, ends_at, created_at, updated_at
+            FROM entitlements
+            WHERE user_id = $1
+            ORDER BY created_at DESC, entitlement_id DESC
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(rows.into_iter().map(Entitlement::from).collect())
+    }
+
+    async fn get_entitlement(&self, entitlement_id: i64) -> Result<Option<Entitlement>> {
+        let row = sqlx::query_as::<_, EntitlementRow>(
+            r"
+            SELECT
+                entitlement_id, user_id, sku_id, entitlement_type, is_test, consumed,
+                starts_at, ends_at, created_at, updated_at
+            FROM entitlements
+            WHERE entitlement_id = $1
+            ",
+        )
+        .bind(entitlement_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(row.map(Entitlement::from))
+    }
+
+    async fn delete_entitlement(&self, entitlement_id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(StorageError::Database)?;
+        sqlx::query("DELETE FROM entitlements WHERE entitlement_id = $1")
+            .bind(entitlement_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(StorageError::Database)?;
+
+        sqlx::query(
+            r"
+            INSERT INTO deleted_entitlements (entitlement_id)
+            VALUES ($1)
+            ON CONFLICT (entitlement_id) DO NOTHING
+            ",
+        )
+        .bind(entitlement_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        tx.commit().await.map_err(StorageError::Database)?;
+        Ok(())
+    }
+
+    async fn get_entitlement_deleted_at(
+        &self,
+        entitlement_id: i64,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let deleted_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+            r"
+            SELECT deleted_at
+            FROM deleted_entitlements
+            WHERE entitlement_id = $1
+            ",
+        )
+        .bind(entitlement_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(deleted_at)
+    }
+
+    async fn record_subscription_decision(&self, decision: &SubscriptionDecision) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO subscription_decisions
+                (user_id, action, previous_tier, previous_source, tier, source, expires_at, reason, decided_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ",
+        )
+        .bind(decision.user_id)
+        .bind(decision.action)
+        .bind(decision.previous_tier)
+        .bind(decision.previous_source)
+        .bind(decision.tier)
+        .bind(decision.source)
+        .bind(decision.expires_at)
+        .bind(&decision.reason)
+        .bind(decision.decided_at)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn get_subscription_decisions(
+        &self,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SubscriptionDecision>> {
+        let rows = sqlx::query_as::<_, SubscriptionDecisionRow>(
+            r"
+            SELECT
+                user_id, action, previous_tier, previous_source, tier, source,
+                expires_at, reason, decided_at
+            FROM subscription_decisions
+            WHERE user_id = $1
+            ORDER BY decided_at DESC, id DESC
+            LIMIT $2
+            ",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(rows.into_iter().map(SubscriptionDecision::from).collect())
+    }
+}
+
+#[async_trait]
+impl SessionStorage for SqlxStorage {
+    async fn create_session(&self, params: SessionCreateParams<'_>) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO sessions (session_id, user_id, user_agent, ip_address, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ",
+        )
+        .bind(params.session_id)
+        .bind(params.user_id)
+        .bind(params.user_agent)
+        .bind(params.ip_address)
+        .bind(params.expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>> {
+        let row = sqlx::query_as::<_, SessionRow>(
+            r"
+            SELECT session_id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at, revoked_at
+            FROM sessions
+            WHERE session_id = $1
+            ",
+        )
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn list_user_sessions(&self, user_id: i64) -> Result<Vec<Session>> {
+        let rows = sqlx::query_as::<_, SessionRow>(
+            r"
+            SELECT session_id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at, revoked_at
+            FROM sessions
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY last_seen_at DESC
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        sqlx::query(
+            r"
+            UPDATE sessions
+            SET last_seen_at = NOW(), expires_at = COALESCE($2, expires_at)
+            WHERE session_id = $1
+            ",
+        )
+        .bind(session_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn revoke_session(&self, session_id: Uuid) -> Result<()> {
+        sqlx::query(
+            r"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE session_id = $1 AND revoked_at IS NULL
+            ",
+        )
+        .bind(session_id)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: i64) -> Result<()> {
+        sqlx::query(
+            r"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn create_ws_ticket(&self, params: WsTicketParams<'_>) -> Result<()> {
+        // Tickets that were never used are dropped once they expire
+        sqlx::query(
+            r"
+            DELETE FROM ws_tickets
+            WHERE expires_at <= NOW()
+            ",
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        sqlx::query(
+            r"
+            INSERT INTO ws_tickets (ticket_hash, user_id, username, scopes, roles, session_id, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ",
+        )
+        .bind(params.ticket_hash)
+        .bind(params.user_id)
+        .bind(params.username)
+        .bind(params.scopes)
+        .bind(params.roles)
+        .bind(params.session_id)
+        .bind(params.expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn consume_ws_ticket(&self, ticket_hash: &str) -> Result<Option<WsTicket>> {
+        let row = sqlx::query_as::<_, WsTicketRow>(
+            r"
+            DELETE FROM ws_tickets
+            WHERE ticket_hash = $1
+            RETURNING user_id, username, scopes, roles, session_id, expires_at
+            ",
+        )
+        .bind(ticket_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(row
+            .map(WsTicket::from)
+            .filter(|ticket| ticket.expires_at > Utc::now()))
+    }
+
+    async fn consume_oauth_state(&self, nonce: &str, expires_at: DateTime<Utc>) -> Result<bool> {
+        // Expired states are rejected before reaching storage, so their records can go
+        sqlx::query(
+            r"
+            DELETE FROM oauth_states
+            WHERE expires_at <= NOW()
+            ",
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        let result = sqlx::query(
+            r"
+            INSERT INTO oauth_states (nonce, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (nonce) DO NOTHING
+            ",
+        )
+        .bind(nonce)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[async_trait]
+impl RoleStorage for SqlxStorage {
+    async fn upsert_role(&self, name: &str, permissions: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(StorageError::Database)?;
+
+        sqlx::query(
+            r"
+            INSERT INTO roles (name)
+            VALUES ($1)
+            ON CONFLICT (name) DO NOTHING
+            ",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        sqlx::query(
+            r"
+            DELETE FROM role_permissions
+            WHERE role = $1
+            ",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        sqlx::query(
+            r"
+            INSERT INTO role_permissions (role, permission)
+            SELECT $1, permission FROM UNNEST($2::TEXT[]) AS permission
+            ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(name)
+        .bind(permissions)
+        .execute(&mut *tx)
+        .await
+        .map_err(StorageError::Database)?;
+
+        tx.commit().await.map_err(StorageError::Database)?;
+        Ok(())
+    }
+
+    async fn grant_role(&self, user_id: i64, role: &str) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO user_roles (user_id, role)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id, role) DO NOTHING
+            ",
+        )
+        .bind(user_id)
+        .bind(role)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn revoke_role(&self, user_id: i64, role: &str) -> Result<()> {
+        sqlx::query(
+            r"
+            DELETE FROM user_roles
+            WHERE user_id = $1 AND role = $2
+            ",
+        )
+        .bind(user_id)
+        .bind(role)
+        .execute(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(())
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>> {
+        let roles = sqlx::query_scalar::<_, String>(
+            r"
+            SELECT role
+            FROM user_roles
+            WHERE user_id = $1
+            ORDER BY role
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(roles)
+    }
+
+    async fn get_user_permissions(&self, user_id: i64) -> Result<Vec<String>> {
+        let permissions = sqlx::query_scalar::<_, String>(
+            r"
+            SELECT DISTINCT rp.permission
+            FROM user_roles ur
+            JOIN role_permissions rp ON rp.role = ur.role
+            WHERE ur.user_id = $1
+            ORDER BY rp.permission
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(StorageError::Database)?;
+
+        Ok(permissions)
+    }
 }
 
 /// Internal row type for `SQLx` queries.
@@ -234,9 +897,145 @@ struct UserRow {
     avatar_url: Option<String>,
     refresh_token: Option<String>,
     token_expires_at: Option<DateTime<Utc>>,
+    granted_scopes: Vec<String>,
+    token_version: i32,
     subscription_tier: SubscriptionTier,
     subscription_source: Option<SubscriptionSource>,
     subscription_expires_at: Option<DateTime<Utc>>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
+
+impl UserRow {
+    fn into_user(self, refresh_token: Option<String>) -> User {
+        User {
+            user_id: self.user_id,
+            username: self.username,
+            global_name: self.global_name,
+            avatar_url: self.avatar_url,
+            refresh_token,
+            token_expires_at: self.token_expires_at,
+            granted_scopes: self.granted_scopes,
+            token_version: self.token_version,
+            subscription_tier: self.subscription_tier,
+            subscription_source: self.subscription_source,
+            subscription_expires_at: self.subscription_expires_at,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// Internal row type for entitlement queries.
+#[derive(Debug, sqlx::FromRow)]
+struct EntitlementRow {
+    entitlement_id: i64,
+    user_id: i64,
+    sku_id: i64,
+    entitlement_type: i32,
+    is_test: bool,
+    consumed: bool,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<EntitlementRow> for Entitlement {
+    fn from(row: EntitlementRow) -> Self {
+        Self {
+            entitlement_id: row.entitlement_id,
+            user_id: row.user_id,
+            sku_id: row.sku_id,
+            entitlement_type: row.entitlement_type,
+            is_test: row.is_test,
+            consumed: row.consumed,
+            starts_at: row.starts_at,
+            ends_at: row.ends_at,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Internal row type for subscription decision queries.
+#[derive(Debug, sqlx::FromRow)]
+struct SubscriptionDecisionRow {
+    user_id: i64,
+    action: SubscriptionAction,
+    previous_tier: SubscriptionTier,
+    previous_source: Option<SubscriptionSource>,
+    tier: SubscriptionTier,
+    source: Option<SubscriptionSource>,
+    expires_at: Option<DateTime<Utc>>,
+    reason: String,
+    decided_at: DateTime<Utc>,
+}
+
+impl From<SubscriptionDecisionRow> for SubscriptionDecision {
+    fn from(row: SubscriptionDecisionRow) -> Self {
+        Self {
+            user_id: row.user_id,
+            action: row.action,
+            previous_tier: row.previous_tier,
+            previous_source: row.previous_source,
+            tier: row.tier,
+            source: row.source,
+            expires_at: row.expires_at,
+            reason: row.reason,
+            decided_at: row.decided_at,
+        }
+    }
+}
+
+/// Internal row type for session queries.
+#[derive(Debug, sqlx::FromRow)]
+struct SessionRow {
+    session_id: Uuid,
+    user_id: i64,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<SessionRow> for Session {
+    fn from(row: SessionRow) -> Self {
+        Self {
+            session_id: row.session_id,
+            user_id: row.user_id,
+            user_agent: row.user_agent,
+            ip_address: row.ip_address,
+            created_at: row.created_at,
+            last_seen_at: row.last_seen_at,
+            expires_at: row.expires_at,
+            revoked_at: row.revoked_at,
+        }
+    }
+}
+
+/// Internal row type for WebSocket ticket queries.
+#[derive(Debug, sqlx::FromRow)]
+struct WsTicketRow {
+    user_id: i64,
+    username: String,
+    scopes: Vec<String>,
+    roles: Vec<String>,
+    session_id: Option<Uuid>,
+    expires_at: DateTime<Utc>,
+}
+
+impl From<WsTicketRow> for WsTicket {
+    fn from(row: WsTicketRow) -> Self {
+        Self {
+            user_id: row.user_id,
+            username: row.username,
+            scopes: row.scopes,
+            roles: row.roles,
+            session_id: row.session_id,
+            expires_at: row.expires_at,
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Test support: an in-process fake of the Discord API.
+//!
+//! [`MockDiscord`] serves the token, revoke, `users/@me` and entitlements
+//! endpoints on a local port. Point `DiscordConfig::api_base_url` at
+//! [`MockDiscord::api_base_url`] to exercise the auth routes and entitlement
+//! syncing without network access.
+//!
+//! Each endpoint answers with a default response, which can be replaced with
+//! [`MockDiscord::respond`] or preceded by one-shot responses queued with
+//! [`MockDiscord::enqueue`]. Every request is recorded for assertions.
+//!
+//! [`MockDiscord::sign`] signs webhook event and interaction bodies with the
+//! key whose public half is in [`MockDiscord::config`], the way Discord does.
+//!
+//! With `memory-storage`, [`MockDiscord::state_with_user`] builds an
+//! `AppState` around the fake with premium SKU 777 and a stored user.
+//!
+//! Enabled by the `test-util` feature.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{
+    body::Bytes,
+    extract::{OriginalUri, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use ed25519_dalek::{Signer, SigningKey};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, sync::oneshot};
+
+use crate::{
+    discord::{SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    models::UserUpsertParams,
+    AppState, Config,
+};
+
+/// Seed of the key [`MockDiscord::sign`] signs requests with.
+const MOCK_SIGNING_KEY: [u8; 32] = [7; 32];
+
+/// A Discord API endpoint served by [`MockDiscord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockEndpoint {
+    /// `POST /oauth2/token`, for both code exchange and refresh.
+    Token,
+    /// `POST /oauth2/token/revoke`.
+    Revoke,
+    /// `GET /users/@me`.
+    CurrentUser,
+    /// `GET /applications/{id}/entitlements`.
+    Entitlements,
+}
+
+/// A scripted response from [`MockDiscord`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    /// HTTP status code.
+    pub status: StatusCode,
+    /// JSON body.
+    pub body: Value,
+    /// Extra response headers, e.g. `Retry-After`.
+    pub headers: Vec<(String, String)>,
+    /// How long to wait before answering, after the request is recorded.
+    pub delay: Option<Duration>,
+}
+
+impl MockResponse {
+    /// A `200 OK` response with a JSON body.
+    #[must_use]
+    pub fn json(body: Value) -> Self {
+        Self {
+            status: StatusCode::OK,
+            body,
+            headers: Vec::new(),
+            delay: None,
+        }
+    }
+
+    /// An error response with Discord's `{"message", "code"}` body.
+    #[must_use]
+    pub fn error(status: StatusCode) -> Self {
+        Self {
+            status,
+            body: json!({"message": status.canonical_reason().unwrap_or("error"), "code": 0}),
+            headers: Vec::new(),
+            delay: None,
+        }
+    }
+
+    /// Add a response header.
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Hold the response back for `delay`, e.g. to act while a request is in flight.
+    #[must_use]
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// The default `/oauth2/token` response: a week-long grant of `identify email`.
+    #[must_use]
+    pub fn token() -> Self {
+        Self::json(json!({
+            "access_token": "mock_access_token",
+            "token_type": "Bearer",
+            "expires_in": 604_800,
+            "refresh_token": "mock_refresh_token",
+            "scope": "identify email",
+        }))
+    }
+
+    /// A `/users/@me` response for the given user.
+    #[must_use]
+    pub fn user(user_id: i64, username: &str) -> Self {
+        Self::json(json!({
+            "id": user_id.to_string(),
+            "username": username,
+            "avatar": null,
+            "global_name": null,
+            "discriminator": "0",
+        }))
+    }
+
+    /// An entitlements response; build items with [`mock_entitlement`].
+    #[must_use]
+    pub fn entitlements(entitlements: Vec<Value>) -> Self {
+        Self::json(Value::Array(entitlements))
+    }
+}
+
+/// An active, non-expiring application subscription entitlement.
+#[must_use]
+pub fn mock_entitlement(entitlement_id: i64, user_id: i64, sku_id: i64) -> Value {
+    json!({
+        "id": entitlement_id.to_string(),
+        "sku_id": sku_id.to_string(),
+        "user_id": user_id.to_string(),
+        "type": 8,
+        "deleted": false,
+        "starts_at": null,
+        "ends_at": null,
+        "consumed": false,
+    })
+}
+
+/// A request received by [`MockDiscord`].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// Which endpoint was called.
+    pub endpoint: MockEndpoint,
+    /// HTTP method.
+    pub method: Method,
+    /// Path and query string, relative to the API base URL.
+    pub uri: String,
+    /// The `Authorization` header, if any.
+    pub authorization: Option<String>,
+    /// Raw request body (form-encoded for the token endpoints).
+    pub body: String,
+}
+
+impl RecordedRequest {
+    /// Decode a form-encoded body field, e.g. `form_value("grant_type")`.
+    #[must_use]
+    pub fn form_value(&self, name: &str) -> Option<String> {
+        serde_urlencoded::from_str::<Vec<(String, String)>>(&self.body)
+            .ok()?
+            .into_iter()
+            .find_map(|(key, value)| (key == name).then_some(value))
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    defaults: HashMap<MockEndpoint, MockResponse>,
+    queued: HashMap<MockEndpoint, VecDeque<MockResponse>>,
+    requests: Vec<RecordedRequest>,
+}
+
+impl MockState {
+    fn next_response(&mut self, endpoint: MockEndpoint) -> MockResponse {
+        if let Some(response) = self.queued.get_mut(&endpoint).and_then(VecDeque::pop_front) {
+            return response;
+        }
+        self.defaults
+            .get(&endpoint)
+            .cloned()
+            .unwrap_or_else(|| default_response(endpoint))
+    }
+}
+
+fn default_response(endpoint: MockEndpoint) -> MockResponse {
+    match endpoint {
+        MockEndpoint::Token => MockResponse::token(),
+        MockEndpoint::Revoke => MockResponse::json(json!({})),
+        MockEndpoint::CurrentUser => MockResponse::user(123_456_789, "mock_user"),
+        MockEndpoint::Entitlements => MockResponse::entitlements(Vec::new()),
+    }
+}
+
+/// An in-process fake of the Discord API, shut down when dropped.
+pub struct MockDiscord {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockDiscord {
+    /// Start the fake on a random local port.
+    ///
+    /// # Errors
+    ///    - Returns an error if the listener cannot be bound.
+    pub async fn start() -> std::io::Result<Self> {
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let app = Router::new().fallback(dispatch).with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (shutdown, signal) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = signal.await;
+                })
+                .await;
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Base URL to use as `DiscordConfig::api_base_url`.
+    #[must_use]
+    pub fn api_base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A `Config` with test credentials whose Discord calls go to this fake.
+    #[must_use]
+    pub fn config(&self) -> Config {
+        serde_json::from_value(json!({
+            "discord": {
+                "client_id": "mock_client_id",
+                "client_secret": "mock_client_secret",
+                "redirect_uri": "http://localhost/callback",
+                "bot_token": "mock_bot_token",
+                "api_base_url": self.api_base_url(),
+                "public_key": hex::encode(mock_signing_key().verifying_key().as_bytes()),
+            },
+            "security": {
+                "jwt_secret": "mock_jwt_secret",
+                "encryption_key": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            },
+        }))
+        .expect("mock config is valid")
+    }
+
+    /// Replace the response an endpoint gives once its queue is empty.
+    pub fn respond(&self, endpoint: MockEndpoint, response: MockResponse) {
+        self.state.lock().defaults.insert(endpoint, response);
+    }
+
+    /// Queue a one-shot response, served before the endpoint's default.
+    pub fn enqueue(&self, endpoint: MockEndpoint, response: MockResponse) {
+        self.state
+            .lock()
+            .queued
+            .entry(endpoint)
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Every request received so far, oldest first.
+    #[must_use]
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().requests.clone()
+    }
+
+    /// Requests received by one endpoint, oldest first.
+    #[must_use]
+    pub fn requests_to(&self, endpoint: MockEndpoint) -> Vec<RecordedRequest> {
+        self.state
+            .lock()
+            .requests
+            .iter()
+            .filter(|request| request.endpoint == endpoint)
+            .cloned()
+            .collect()
+    }
+
+    /// Signature headers for a request body, as Discord sends with webhook
+    /// events and interactions.
+    #[must_use]
+    pub fn sign(&self, body: &[u8]) -> HeaderMap {
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let message = [timestamp.as_bytes(), body].concat();
+        let signature = mock_signing_key().sign(&message);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&hex::encode(signature.to_bytes()))
+                .expect("hex is a valid header value"),
+        );
+        headers.insert(
+            TIMESTAMP_HEADER,
+            HeaderValue::from_str(&timestamp).expect("digits are a valid header value"),
+        );
+        headers
+    }
+
+    /// An `AppState` over `MemoryStorage` whose Discord calls go to this fake,
+    /// with premium SKU 777 configured and `user_id` stored.
+    ///
+    /// # Panics
+    ///    Panics if the user cannot be stored.
+    #[cfg(feature = "memory-storage")]
+    pub async fn state_with_user(&self, user_id: i64) -> AppState {
+        let mut config = self.config();
+        config.discord.premium_sku_id = Some(777);
+        let state = AppState::new(config, crate::storage::MemoryStorage::new());
+        store_mock_user(&state, user_id).await;
+        state
+    }
+}
+
+/// Store a free user named `alice` with no Discord tokens.
+///
+/// # Panics
+///    Panics if the user cannot be stored.
+pub async fn store_mock_user(state: &AppState, user_id: i64) {
+    state
+        .storage
+        .upsert_user(
+            UserUpsertParams {
+                user_id,
+                username: "alice",
+                global_name: None,
+                avatar_url: None,
+                refresh_token: None,
+                token_expires_at: None,
+                granted_scopes: None,
+            },
+            &state.config.security.encryption_key,
+        )
+        .await
+        .expect("mock user can be stored");
+}
+
+fn mock_signing_key() -> SigningKey {
+    SigningKey::from_bytes(&MOCK_SIGNING_KEY)
+}
+
+impl Drop for MockDiscord {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Route a request to its endpoint, record it and answer with its next response.
+async fn dispatch(
+    State(state): State<Arc<Mutex<MockState>>>,
+    method: Method,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path();
+    let endpoint = match (&method, path) {
+        (&Method::POST, "/oauth2/token") => MockEndpoint::Token,
+        (&Method::POST, "/oauth2/token/revoke") => MockEndpoint::Revoke,
+        (&Method::GET, "/users/@me") => MockEndpoint::CurrentUser,
+        (&Method::GET, _)
+            if path.starts_with("/applications/") && path.ends_with("/entitlements") =>
+        {
+            MockEndpoint::Entitlements
+        }
+        _ => return MockResponse::error(StatusCode::NOT_FOUND).into_response(),
+    };
+
+    let response = {
+        let mut state = state.lock();
+        state.requests.push(RecordedRequest {
+            endpoint,
+            method,
+            uri: uri.to_string(),
+            authorization: headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from),
+            body: String::from_utf8_lossy(&body).into_owned(),
+        });
+        state.next_response(endpoint)
+    };
+    if let Some(delay) = response.delay {
+        tokio::time::sleep(delay).await;
+    }
+    response.into_response()
+}
+
+impl IntoResponse for MockResponse {
+    fn into_response(self) -> Response {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+        (self.status, headers, Json(self.body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_discord_scripts_and_records_responses() {
+        let discord = MockDiscord::start().await.unwrap();
+        discord.enqueue(
+            MockEndpoint::Token,
+            MockResponse::error(StatusCode::TOO_MANY_REQUESTS).with_header("retry-after", "1"),
+        );
+        let client = reqwest::Client::new();
+        let url = format!("{}/oauth2/token", discord.api_base_url());
+
+        let response = client
+            .post(&url)
+            .form(&[("grant_type", "refresh_token")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429);
+        assert_eq!(response.headers()["retry-after"], "1");
+
+        let response = client.post(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["access_token"], "mock_access_token");
+
+        let requests = discord.requests_to(MockEndpoint::Token);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].form_value("grant_type").as_deref(),
+            Some("refresh_token")
+        );
+        assert!(discord.requests_to(MockEndpoint::Revoke).is_empty());
+    }
+}
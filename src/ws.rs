@@ -0,0 +1,227 @@
+//! WebSocket hub for pushing messages to connected users.
+//!
+//! The hub keeps a registry of open WebSocket connections per `user_id`. The
+//! host application can push JSON messages to one user or broadcast them to
+//! everyone, and every connection forwards the account events published on
+//! the [`EventBus`] for its user, such as subscription changes and forced
+//! logouts.
+//!
+//! Connections are accepted by the `GET /ws` route in `auth_router()`, or by
+//! any handler that authenticates a user and calls [`WsHub::serve`]:
+//!
+//! ```rust,ignore
+//! async fn my_ws(
+//!     user: AuthenticatedUser,
+//!     ws: WebSocketUpgrade,
+//!     State(state): State<Arc<AppState>>,
+//! ) -> Response {
+//!     ws.on_upgrade(move |socket| async move { state.ws_hub.serve(socket, user).await })
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::extract::ws::{Message, WebSocket};
+use parking_lot::RwLock;
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{auth::AuthenticatedUser, events::EventBus};
+
+/// Messages queued per connection before further messages are dropped.
+const CONNECTION_QUEUE_CAPACITY: usize = 64;
+
+/// Registry of open WebSocket connections, keyed by user.
+#[derive(Debug)]
+pub struct WsHub {
+    connections: RwLock<HashMap<i64, Vec<Connection>>>,
+    next_id: AtomicU64,
+    events: EventBus,
+}
+
+#[derive(Debug)]
+struct Connection {
+    id: u64,
+    sender: mpsc::Sender<Message>,
+}
+
+impl Connection {
+    /// Queue a message without waiting; returns false if it was dropped.
+    fn push(&self, message: Message) -> bool {
+        self.sender.try_send(message).is_ok()
+    }
+}
+
+impl WsHub {
+    /// Create an empty hub whose connections forward events from `events`.
+    #[must_use]
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            connections: RwLock::default(),
+            next_id: AtomicU64::default(),
+            events,
+        }
+    }
+
+    /// Run a WebSocket connection for an authenticated user until it closes.
+    ///
+    /// The connection is registered for the duration of the call and forwards
+    /// the user's account events. A `forced_logout` event for the connection's
+    /// session (or for all sessions) is sent and then the connection is
+    /// closed. Messages sent by the client are ignored; the hub only pushes to
+    /// clients.
+    pub async fn serve(&self, mut socket: WebSocket, user: AuthenticatedUser) {
+        let mut events = self.events.subscribe();
+        let (id, mut receiver) = self.register(user.user_id);
+        tracing::debug!("WebSocket {} connected for user {}", id, user.user_id);
+
+        loop {
+            tokio::select! {
+                outbound = receiver.recv() => {
+                    let Some(message) = outbound else { break };
+                    if socket.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(event) if event.user_id == user.user_id => {
+                        let Some(message) = to_message(&event.event) else { continue };
+                        if socket.send(message).await.is_err() {
+                            break;
+                        }
+                        if event.event.ends_session(user.session_id) {
+                            let _ = socket.send(Message::Close(None)).await;
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("WebSocket {} missed {} account events", id, skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                inbound = socket.recv() => match inbound {
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+
+        self.unregister(user.user_id, id);
+        tracing::debug!("WebSocket {} closed for user {}", id, user.user_id);
+    }
+
+    /// Send a JSON message to every connection of a user.
+    ///
+    /// Returns the number of connections the message was queued on.
+    pub fn send_to_user<T: Serialize>(&self, user_id: i64, message: &T) -> usize {
+        let Some(message) = to_message(message) else {
+            return 0;
+        };
+
+        self.connections
+            .read()
+            .get(&user_id)
+            .map_or(0, |connections| {
+                connections
+                    .iter()
+                    .filter(|connection| connection.push(message.clone()))
+                    .count()
+            })
+    }
+
+    /// Send a JSON message to every connected user.
+    ///
+    /// Returns the number of connections the message was queued on.
+    pub fn broadcast<T: Serialize>(&self, message: &T) -> usize {
+        let Some(message) = to_message(message) else {
+            return 0;
+        };
+
+        self.connections
+            .read()
+            .values()
+            .flatten()
+            .filter(|connection| connection.push(message.clone()))
+            .count()
+    }
+
+    /// Number of open connections across all users.
+    #[must_use]
+    pub fn connection_count(&self) -> usize {
+        self.connections.read().values().map(Vec::len).sum()
+    }
+
+    /// Number of open connections for one user.
+    #[must_use]
+    pub fn user_connection_count(&self, user_id: i64) -> usize {
+        self.connections.read().get(&user_id).map_or(0, Vec::len)
+    }
+
+    fn register(&self, user_id: i64) -> (u64, mpsc::Receiver<Message>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(CONNECTION_QUEUE_CAPACITY);
+        self.connections
+            .write()
+            .entry(user_id)
+            .or_default()
+            .push(Connection { id, sender });
+        (id, receiver)
+    }
+
+    fn unregister(&self, user_id: i64, id: u64) {
+        let mut connections = self.connections.write();
+        if let Some(user_connections) = connections.get_mut(&user_id) {
+            user_connections.retain(|connection| connection.id != id);
+            if user_connections.is_empty() {
+                connections.remove(&user_id);
+            }
+        }
+    }
+}
+
+/// Serialize a message as a JSON text frame.
+fn to_message<T: Serialize>(message: &T) -> Option<Message> {
+    match serde_json::to_string(message) {
+        Ok(json) => Some(Message::Text(json.into())),
+        Err(e) => {
+            tracing::error!("Failed to serialize WebSocket message: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(message: Message) -> String {
+        match message {
+            Message::Text(text) => text.to_string(),
+            other => panic!("expected a text message, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_to_user_and_broadcast() {
+        let hub = WsHub::new(EventBus::new());
+        let (first, mut first_rx) = hub.register(1);
+        let (_, mut second_rx) = hub.register(1);
+        let (_, mut other_rx) = hub.register(2);
+        assert_eq!(hub.connection_count(), 3);
+        assert_eq!(hub.user_connection_count(1), 2);
+
+        assert_eq!(hub.send_to_user(1, &serde_json::json!({"hello": 1})), 2);
+        assert_eq!(text(first_rx.recv().await.unwrap()), r#"{"hello":1}"#);
+        assert_eq!(text(second_rx.recv().await.unwrap()), r#"{"hello":1}"#);
+        assert!(other_rx.try_recv().is_err());
+        assert_eq!(hub.send_to_user(3, &"nobody"), 0);
+
+        assert_eq!(hub.broadcast(&"all"), 3);
+        assert_eq!(text(other_rx.recv().await.unwrap()), r#""all""#);
+
+        hub.unregister(1, first);
+        assert_eq!(hub.user_connection_count(1), 1);
+    }
+}